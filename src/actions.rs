@@ -1,41 +1,82 @@
+use crate::backup;
 use crate::db;
+use crate::download;
+use crate::error::{no_episode, no_podcast, Error, OrNotFound};
 use crate::feed;
-use anyhow::Result;
+use crate::player;
+use crate::podcast;
+use crate::tag;
+use crate::template;
+use anyhow::{anyhow, Result};
 use opml::OPML;
-use std::sync::mpsc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{mpsc, Condvar, Mutex};
+use std::time::Duration;
 
 pub trait Action {
-    fn execute(&self, tx: mpsc::Sender<String>, conn: rusqlite::Connection) -> Result<()>;
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()>;
+}
+
+// What an action reports while it runs, so whoever shows it can tell progress from results
+// from problems. `Failed` is for one part of the work going wrong while the rest carries
+// on; an error that stops the whole action is what `execute` returns instead.
+pub enum ActionEvent {
+    // Work on something has begun, e.g. fetching a feed.
+    Started(String),
+    // How far the work has got, in bytes for downloads. Servers don't always say how much
+    // there is in all.
+    Progress { done: u64, total: Option<u64> },
+    // A line of the action's results, e.g. one podcast of a listing.
+    Output(String),
+    PodcastUpdated { title: String, new_episodes: usize },
+    EpisodeAdded { id: i64, title: String },
+    // Something worth knowing that didn't stop anything.
+    Warning(String),
+    Failed { error: String },
+    // The action is done, with a word on how it went.
+    Finished(String),
 }
 
 pub struct List {
     pub id: Option<i64>,
     pub detailed: bool,
     pub limit: Option<i64>,
+    pub category: Option<String>,
+    pub tagged: Option<String>,
 }
 
-impl Action for List {
-    fn execute(self: &List, tx: mpsc::Sender<String>, conn: rusqlite::Connection) -> Result<()> {
-        let limit = if let Some(l) = self.limit { l } else { 0 };
+impl List {
+    // The podcasts to list: just the one asked for, or all of them in the category and
+    // with the tag.
+    pub fn select(&self, conn: &rusqlite::Connection) -> Result<Vec<podcast::Podcast>> {
         if let Some(id) = self.id {
-            let pod = db::fetch_podcast(&conn, id)?;
-            tx.send(pod.str(self.detailed))?;
-            for ep in pod.episodes.iter().take(if limit > 0 {
-                limit as usize
-            } else {
-                pod.episodes.len()
-            }) {
-                tx.send(ep.str(self.detailed))?;
-            }
-        } else {
-            let pods = db::fetch_all_podcasts(&conn)?;
-            for pod in pods.iter().take(if limit > 0 {
-                limit as usize
-            } else {
-                pods.len()
-            }) {
-                tx.send(pod.str(self.detailed))?;
-            }
+            return Ok(vec![
+                db::fetch_podcast(conn, id).or_not_found(|| no_podcast(id))?
+            ]);
+        }
+        let mut pods = db::fetch_all_podcasts(conn)?;
+        if let Some(category) = &self.category {
+            pods.retain(|pod| pod.in_category(category));
+        }
+        if let Some(tag) = &self.tagged {
+            pods.retain(|pod| pod.has_tag(tag));
+        }
+        if let Some(limit) = self.limit.filter(|l| *l > 0) {
+            pods.truncate(limit as usize);
+        }
+        Ok(pods)
+    }
+}
+
+impl Action for List {
+    fn execute(
+        self: &List,
+        tx: mpsc::Sender<ActionEvent>,
+        conn: rusqlite::Connection,
+    ) -> Result<()> {
+        for pod in self.select(&conn)? {
+            tx.send(ActionEvent::Output(pod.str(self.detailed)))?;
         }
         Ok(())
     }
@@ -46,37 +87,126 @@ pub struct Add {
 }
 
 impl Action for Add {
-    fn execute(self: &Add, tx: mpsc::Sender<String>, conn: rusqlite::Connection) -> Result<()> {
-        tx.send(format!("Fetching {}...", self.url))?;
-        let rss = feed::fetch_rss(&self.url)?;
-        let mut pod = feed::parse_rss(&self.url, &rss)?;
-        db::insert_podcast(&conn, &mut pod)?;
-        tx.send(format!("Added {}.", pod.title))?;
+    fn execute(
+        self: &Add,
+        tx: mpsc::Sender<ActionEvent>,
+        conn: rusqlite::Connection,
+    ) -> Result<()> {
+        let pod = self.run(&tx, &conn)?;
+        tx.send(ActionEvent::Finished(format!(
+            "Added {} with {} episodes.",
+            pod.title,
+            pod.episodes.len()
+        )))?;
         Ok(())
     }
 }
 
+impl Add {
+    // Adds the podcast and hands it back, episodes and all.
+    pub fn run(
+        &self,
+        tx: &mpsc::Sender<ActionEvent>,
+        conn: &rusqlite::Connection,
+    ) -> Result<podcast::Podcast> {
+        if let Some(id) = db::fetch_podcast_id_by_url(conn, &self.url)? {
+            return Err(anyhow!("{} is already podcast {}.", self.url, id));
+        }
+        tx.send(ActionEvent::Started(format!("Fetching {}...", self.url)))?;
+        let client = reqwest::blocking::Client::new();
+        let (mut pod, cache) = fetch_new(&client, &self.url)?;
+        store_new(conn, &mut pod, &cache)?;
+        Ok(pod)
+    }
+}
+
+// Fetches and parses a feed we don't have yet. This half of adding a podcast doesn't touch
+// the database, so it can run on any thread.
+fn fetch_new(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> Result<(podcast::Podcast, podcast::FeedCache)> {
+    let (rss, cache) = match feed::fetch_rss(client, url, &Default::default())? {
+        feed::Fetched::Modified(rss, cache) => (rss, cache),
+        // Nothing was cached, so only a misbehaving server can get us here.
+        feed::Fetched::Unchanged(_) => {
+            return Err(anyhow!("{} answered Not Modified to a fresh request.", url))
+        }
+    };
+    Ok((feed::parse_feed(url, &rss)?, cache))
+}
+
+fn store_new(
+    conn: &rusqlite::Connection,
+    pod: &mut podcast::Podcast,
+    cache: &podcast::FeedCache,
+) -> Result<()> {
+    db::insert_podcast(conn, pod)?;
+    db::save_feed_cache(conn, pod.id, cache)?;
+    Ok(())
+}
+
 pub struct Episodes {
-    pub id: i64,
+    // One podcast's episodes, or else those of every podcast with the tag.
+    pub id: Option<i64>,
+    pub tagged: Option<String>,
     pub detailed: bool,
     pub limit: Option<i64>,
+    pub filter: podcast::EpisodeFilter,
+}
+
+impl Episodes {
+    // The episodes that get past the filter, up to the limit. Episodes from several
+    // podcasts come newest first.
+    pub fn select(&self, conn: &rusqlite::Connection) -> Result<Vec<podcast::Episode>> {
+        let mut episodes = match (self.id, &self.tagged) {
+            (Some(id), _) => {
+                db::fetch_podcast_and_episodes(conn, id)
+                    .or_not_found(|| no_podcast(id))?
+                    .episodes
+            }
+            (None, Some(tag)) => {
+                let mut episodes = Vec::new();
+                for pod in tagged_podcasts(conn, tag)? {
+                    episodes.extend(db::fetch_episodes(conn, pod.id)?);
+                }
+                episodes.sort_by(|a, b| b.pub_date.cmp(&a.pub_date));
+                episodes
+            }
+            (None, None) => return Err(anyhow!("Say which podcast, by id or by tag.")),
+        };
+        episodes.retain(|ep| self.filter.matches(ep));
+        if let Some(limit) = self.limit {
+            episodes.truncate(limit.max(0) as usize);
+        }
+        if self.detailed {
+            for ep in episodes.iter_mut() {
+                db::fetch_episode_extras(conn, ep)?;
+            }
+        }
+        Ok(episodes)
+    }
+}
+
+// The podcasts with the tag, or its subtags. Having none is an error, since it's more
+// likely a misspelt tag than a deliberately empty one.
+fn tagged_podcasts(conn: &rusqlite::Connection, tag: &str) -> Result<Vec<podcast::Podcast>> {
+    let mut pods = db::fetch_all_podcasts(conn)?;
+    pods.retain(|pod| pod.has_tag(tag));
+    if pods.is_empty() {
+        return Err(Error::NotFound(format!("No podcasts are tagged {}.", tag)).into());
+    }
+    Ok(pods)
 }
 
 impl Action for Episodes {
     fn execute(
         self: &Episodes,
-        tx: mpsc::Sender<String>,
+        tx: mpsc::Sender<ActionEvent>,
         conn: rusqlite::Connection,
     ) -> Result<()> {
-        let pod = db::fetch_podcast_and_episodes(&conn, self.id)?;
-        if let Some(limit) = self.limit {
-            for ep in pod.episodes.iter().take(limit as usize) {
-                tx.send(ep.str(self.detailed))?;
-            }
-        } else {
-            for ep in pod.episodes {
-                tx.send(ep.str(self.detailed))?;
-            }
+        for ep in self.select(&conn)? {
+            tx.send(ActionEvent::Output(ep.str(self.detailed)))?;
         }
         Ok(())
     }
@@ -84,105 +214,1130 @@ impl Action for Episodes {
 
 pub struct Update {
     pub id: Option<i64>,
+    // Only update the podcasts with this tag.
+    pub tagged: Option<String>,
+    // How many feeds are fetched at once.
+    pub jobs: usize,
+    // How long to wait on a single feed before giving up on it.
+    pub timeout: Duration,
+    // Where podcasts with auto-download switched on get their new episodes saved.
+    pub download_dir: PathBuf,
+    pub template: template::Template,
+    // Tag what gets downloaded.
+    pub tag: bool,
+}
+
+// What a worker found when it fetched a feed.
+enum Fetch {
+    Modified(Box<podcast::Podcast>, podcast::FeedCache),
+    Unchanged(podcast::FeedCache),
+}
+
+// Podcasts still waiting to be fetched, and the hosts a worker is fetching from right now.
+// Workers only ever have one request in flight per host, so a big update doesn't hammer a
+// server that hosts many of the feeds.
+struct Jobs {
+    pending: VecDeque<(podcast::Podcast, podcast::FeedCache)>,
+    busy_hosts: HashSet<String>,
+}
+
+// What an update did to one podcast's episodes.
+#[derive(Default)]
+pub struct Changes {
+    // The ids of the episodes that are new.
+    pub new: Vec<i64>,
+    pub edited: usize,
+    pub removed: usize,
+}
+
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} new, {} edited, {} removed",
+            self.new.len(),
+            self.edited,
+            self.removed
+        )
+    }
+}
+
+// How an update went, podcast by podcast, keyed by podcast id and title.
+#[derive(Default)]
+pub struct Summary {
+    pub updated: Vec<(i64, String, Changes)>,
+    pub unchanged: Vec<(i64, String)>,
+    // Along with why.
+    pub failed: Vec<(i64, String, anyhow::Error)>,
+    pub downloaded: usize,
+    pub download_failed: usize,
 }
 
 impl Action for Update {
-    fn execute(&self, tx: mpsc::Sender<String>, conn: rusqlite::Connection) -> Result<()> {
-        if let Some(id) = self.id {
-            let pod = db::fetch_podcast(&conn, id)?;
-            tx.send(format!("Updating {}...", pod.title))?;
-            let rss = feed::fetch_rss(&pod.rss_url)?;
-            let pod = feed::parse_rss(&pod.rss_url, &rss)?;
-            for ep in pod.episodes {
-                db::insert_episode(&conn, &ep, id)?;
-            }
-            tx.send(format!("Updated {}.", pod.title))?;
-        } else {
-            let pods = db::fetch_all_podcasts(&conn)?;
-            for pod in pods {
-                tx.send(format!("Updating {}...", pod.title))?;
-                let rss = feed::fetch_rss(&pod.rss_url);
-                if let Ok(rss) = rss {
-                    if let Ok(pod) = feed::parse_rss(&pod.rss_url, &rss) {
-                        for ep in pod.episodes {
-                            db::insert_episode(&conn, &ep, pod.id)?;
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let summary = self.run(&tx, &conn)?;
+        tx.send(ActionEvent::Finished(summary.lines().join("\n")))?;
+        match summary.error() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Update {
+    // Updates the podcasts, reporting on each as it goes, and sums up how it went.
+    pub fn run(
+        &self,
+        tx: &mpsc::Sender<ActionEvent>,
+        conn: &rusqlite::Connection,
+    ) -> Result<Summary> {
+        let pods = match (self.id, &self.tagged) {
+            (Some(id), _) => vec![db::fetch_podcast(conn, id).or_not_found(|| no_podcast(id))?],
+            (None, Some(tag)) => tagged_podcasts(conn, tag)?,
+            (None, None) => db::fetch_all_podcasts(conn)?,
+        };
+        let mut pending = VecDeque::new();
+        for pod in pods {
+            let cache = db::fetch_feed_cache(conn, pod.id)?;
+            pending.push_back((pod, cache));
+        }
+        let client = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?;
+        let jobs = (
+            Mutex::new(Jobs {
+                pending,
+                busy_hosts: HashSet::new(),
+            }),
+            Condvar::new(),
+        );
+        let mut summary = Summary::default();
+        let mut to_download = Vec::new();
+        std::thread::scope(|scope| -> Result<()> {
+            let (results_tx, results_rx) = mpsc::channel();
+            for _ in 0..self.jobs.max(1) {
+                let (results_tx, tx) = (results_tx.clone(), tx.clone());
+                let (jobs, client) = (&jobs, &client);
+                scope.spawn(move || {
+                    while let Some((pod, cache, host)) = next_job(jobs) {
+                        let _ = tx.send(ActionEvent::Started(format!("Updating {}...", pod.title)));
+                        let fetched = fetch_podcast(client, &pod, &cache);
+                        finish_job(jobs, &host);
+                        if results_tx.send((pod, fetched)).is_err() {
+                            break;
                         }
-                        tx.send(format!("Updated podcast {}.", pod.title))?;
-                    } else {
-                        tx.send(format!("Failed to update podcast {}.", pod.title))?;
                     }
-                } else {
-                    tx.send(format!("Failed to update podcast {}.", pod.title))?;
+                });
+            }
+            // The workers hold the only senders left, so this ends once they're all done.
+            drop(results_tx);
+            // Everything is written from this thread, on the one connection.
+            for (pod, fetched) in results_rx {
+                match fetched {
+                    Ok(Fetch::Modified(parsed, cache)) => {
+                        let changes = store_episodes(tx, conn, pod.id, &parsed.episodes)?;
+                        db::update_podcast(conn, pod.id, &parsed)?;
+                        // Only remember the new body once it has been stored, so a failed
+                        // update is retried in full.
+                        db::save_feed_cache(conn, pod.id, &cache)?;
+                        tx.send(ActionEvent::PodcastUpdated {
+                            title: pod.title.clone(),
+                            new_episodes: changes.new.len(),
+                        })?;
+                        let policy = db::fetch_policy(conn, pod.id)?;
+                        let new = changes.new.clone();
+                        summary.updated.push((pod.id, pod.title.clone(), changes));
+                        if policy.auto_download && !new.is_empty() {
+                            to_download.push((pod, new, policy));
+                        }
+                    }
+                    Ok(Fetch::Unchanged(cache)) => {
+                        db::save_feed_cache(conn, pod.id, &cache)?;
+                        tx.send(ActionEvent::Output(format!("{} unchanged.", pod.title)))?;
+                        summary.unchanged.push((pod.id, pod.title));
+                    }
+                    Err(e) => {
+                        tx.send(ActionEvent::Failed {
+                            error: format!("Failed to update {}: {}", pod.title, e),
+                        })?;
+                        summary.failed.push((pod.id, pod.title, e));
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        // Downloads wait until every feed is in, so a slow one doesn't hold up the rest.
+        for (pod, ids, policy) in to_download {
+            for ep in episodes_to_download(conn, &ids, &policy)? {
+                let result = download_episode(
+                    tx,
+                    conn,
+                    &self.download_dir,
+                    &self.template,
+                    self.tag,
+                    &pod,
+                    &ep,
+                );
+                match result {
+                    Ok(_) => {
+                        tx.send(ActionEvent::Output(format!("Downloaded {}.", ep.title)))?;
+                        summary.downloaded += 1;
+                    }
+                    Err(e) => {
+                        tx.send(ActionEvent::Failed {
+                            error: format!("Failed to download {}: {}", ep.title, e),
+                        })?;
+                        summary.download_failed += 1;
+                    }
                 }
             }
         }
+        Ok(summary)
+    }
+}
+
+// Takes the next podcast whose host isn't busy, waiting for one to free up if need be.
+// Returns None once there's nothing left to fetch.
+fn next_job(
+    jobs: &(Mutex<Jobs>, Condvar),
+) -> Option<(podcast::Podcast, podcast::FeedCache, String)> {
+    let (lock, ready) = jobs;
+    let mut jobs = lock.lock().unwrap();
+    loop {
+        if jobs.pending.is_empty() {
+            return None;
+        }
+        let free = jobs
+            .pending
+            .iter()
+            .position(|(pod, _)| !jobs.busy_hosts.contains(&host_of(&pod.rss_url)));
+        if let Some(i) = free {
+            let (pod, cache) = jobs.pending.remove(i)?;
+            let host = host_of(&pod.rss_url);
+            jobs.busy_hosts.insert(host.clone());
+            return Some((pod, cache, host));
+        }
+        jobs = ready.wait(jobs).unwrap();
+    }
+}
+
+fn finish_job(jobs: &(Mutex<Jobs>, Condvar), host: &str) {
+    let (lock, ready) = jobs;
+    lock.lock().unwrap().busy_hosts.remove(host);
+    ready.notify_all();
+}
+
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+        .unwrap_or_default()
+}
+
+// Fetches and parses a podcast's feed, skipping the parse when the feed hasn't changed since
+// the last update.
+fn fetch_podcast(
+    client: &reqwest::blocking::Client,
+    pod: &podcast::Podcast,
+    cache: &podcast::FeedCache,
+) -> Result<Fetch> {
+    match feed::fetch_rss(client, &pod.rss_url, cache)? {
+        feed::Fetched::Modified(rss, cache) => Ok(Fetch::Modified(
+            Box::new(feed::parse_feed(&pod.rss_url, &rss)?),
+            cache,
+        )),
+        feed::Fetched::Unchanged(cache) => Ok(Fetch::Unchanged(cache)),
+    }
+}
+
+fn store_episodes(
+    tx: &mpsc::Sender<ActionEvent>,
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+    episodes: &[podcast::Episode],
+) -> Result<Changes> {
+    let mut changes = Changes::default();
+    for ep in episodes {
+        match db::upsert_episode(conn, ep, podcast_id)? {
+            db::Upsert::Inserted(id) => {
+                tx.send(ActionEvent::EpisodeAdded {
+                    id,
+                    title: ep.title.clone(),
+                })?;
+                changes.new.push(id);
+            }
+            db::Upsert::Updated => changes.edited += 1,
+            db::Upsert::Unchanged => (),
+        }
+    }
+    // A feed that parses to nothing at all is more likely broken than emptied on purpose.
+    if !episodes.is_empty() {
+        let guids: Vec<&str> = episodes.iter().map(|ep| ep.guid.as_str()).collect();
+        changes.removed = db::mark_removed(conn, podcast_id, &guids)?;
+    }
+    Ok(changes)
+}
+
+// The new episodes worth downloading straight away: the newest first, and no more than the
+// policy would keep anyway.
+fn episodes_to_download(
+    conn: &rusqlite::Connection,
+    ids: &[i64],
+    policy: &podcast::Policy,
+) -> Result<Vec<podcast::Episode>> {
+    let mut eps = ids
+        .iter()
+        .map(|id| db::fetch_episode(conn, *id))
+        .collect::<Result<Vec<_>, _>>()?;
+    eps.retain(|ep| ep.enclosure.is_some());
+    eps.sort_by_key(|ep| std::cmp::Reverse(ep.pub_date.as_deref().and_then(feed::parse_date)));
+    if let Some(keep) = policy.keep_last {
+        eps.truncate(keep.max(0) as usize);
+    }
+    Ok(eps)
+}
+
+// Downloads an episode to where the template puts it under `dir`, reporting progress as it
+// goes, and tags the file when asked to. Trouble with the tags doesn't fail the download.
+fn download_episode(
+    tx: &mpsc::Sender<ActionEvent>,
+    conn: &rusqlite::Connection,
+    dir: &std::path::Path,
+    template: &template::Template,
+    tag: bool,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+) -> Result<download::Download> {
+    tx.send(ActionEvent::Started(format!("Downloading {}...", ep.title)))?;
+    let dest = download::destination(conn, dir, template, pod, ep)?;
+    let dl = download::download(conn, ep, &dest, |done, total| {
+        let _ = tx.send(ActionEvent::Progress { done, total });
+    })?;
+    if let Some(expected) = dl.expected_size {
+        tx.send(ActionEvent::Warning(format!(
+            "The feed said {} is {} bytes, but the file is {} bytes.",
+            ep.title, expected, dl.size
+        )))?;
+    }
+    if tag {
+        match tag::tag_download(conn, &dl.path, pod, ep) {
+            Ok(tagged) => {
+                tx.send(ActionEvent::Output(format!("Tagged {}.", ep.title)))?;
+                if let Some(e) = tagged.artwork_error {
+                    tx.send(ActionEvent::Warning(format!(
+                        "Left the artwork out of {}: {}",
+                        ep.title, e
+                    )))?;
+                }
+            }
+            Err(e) => tx.send(ActionEvent::Failed {
+                error: format!("Failed to tag {}: {}", ep.title, e),
+            })?,
+        }
+    }
+    Ok(dl)
+}
+
+impl Summary {
+    // When feeds failed, an error of the same kind as the first failure, so the exit code
+    // says whether it was the network or a broken feed.
+    pub fn error(&self) -> Option<anyhow::Error> {
+        let (_, _, first) = self.failed.first()?;
+        let message = format!(
+            "{} of {} podcasts failed to update.",
+            self.failed.len(),
+            self.updated.len() + self.unchanged.len() + self.failed.len()
+        );
+        Some(match Error::classify(first) {
+            Some(kind) => kind.reworded(message).into(),
+            None => anyhow!(message),
+        })
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let new: usize = self.updated.iter().map(|(_, _, c)| c.new.len()).sum();
+        lines.push(format!(
+            "Updated {} podcasts with {} new episodes, {} unchanged, {} failed.",
+            self.updated.len(),
+            new,
+            self.unchanged.len(),
+            self.failed.len()
+        ));
+        for (_, title, changes) in &self.updated {
+            if changes.new.len() + changes.edited + changes.removed > 0 {
+                lines.push(format!("  {}: {}", title, changes));
+            }
+        }
+        for (_, title, reason) in &self.failed {
+            lines.push(format!("  {} failed: {}", title, reason));
+        }
+        if self.downloaded + self.download_failed > 0 {
+            lines.push(format!(
+                "Downloaded {} new episodes, {} failed.",
+                self.downloaded, self.download_failed
+            ));
+        }
+        lines
+    }
+}
+
+pub struct Cleanup {
+    // Only report what would be deleted.
+    pub dry_run: bool,
+}
+
+impl Action for Cleanup {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let quota = db::fetch_setting(&conn, db::DISK_QUOTA)?.and_then(|q| q.parse().ok());
+        let downloads = db::fetch_downloads(&conn)?;
+        let used: u64 = downloads.iter().map(|f| f.size).sum();
+        let doomed = cleanup_plan(&conn, downloads, quota)?;
+        // Only what's actually deleted counts, so a file that can't be deleted still takes
+        // up its space below.
+        let mut freed = 0;
+        let mut deleted = 0;
+        for (file, reason) in &doomed {
+            if !self.dry_run {
+                if let Some(path) = &file.episode.download_path {
+                    match std::fs::remove_file(path) {
+                        Ok(()) => (),
+                        // Already gone, so there's only the record left to forget.
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                        Err(e) => {
+                            tx.send(ActionEvent::Failed {
+                                error: format!("Failed to delete {}: {}", path, e),
+                            })?;
+                            continue;
+                        }
+                    }
+                }
+                db::remove_download(&conn, file.episode.id)?;
+            }
+            freed += file.size;
+            deleted += 1;
+            tx.send(ActionEvent::Output(format!(
+                "{} {} ({}, {})",
+                if self.dry_run {
+                    "Would delete"
+                } else {
+                    "Deleted"
+                },
+                file.episode.title,
+                reason,
+                megabytes(file.size)
+            )))?;
+        }
+        if let Some(quota) = quota {
+            let left = used - freed;
+            if left > quota {
+                let what = if deleted == doomed.len() {
+                    "Starred episodes alone take"
+                } else {
+                    "Downloads still take"
+                };
+                tx.send(ActionEvent::Warning(format!(
+                    "{} {}, over the {} quota.",
+                    what,
+                    megabytes(left),
+                    megabytes(quota)
+                )))?;
+            }
+        }
+        tx.send(ActionEvent::Finished(format!(
+            "{} {} in {} files.",
+            if self.dry_run { "Would free" } else { "Freed" },
+            megabytes(freed),
+            deleted
+        )))?;
         Ok(())
     }
 }
 
+// Works out which downloads to delete and why. Each podcast's policy goes first, then the
+// disk quota evicts whatever is left, played episodes before unplayed ones and the oldest
+// downloads first. Starred episodes are never touched.
+fn cleanup_plan(
+    conn: &rusqlite::Connection,
+    downloads: Vec<podcast::DownloadedFile>,
+    quota: Option<u64>,
+) -> Result<Vec<(podcast::DownloadedFile, String)>> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut policies = std::collections::HashMap::new();
+    for file in &downloads {
+        if let std::collections::hash_map::Entry::Vacant(e) = policies.entry(file.podcast_id) {
+            e.insert(db::fetch_policy(conn, file.podcast_id)?);
+        }
+    }
+    // Where each download ranks among its podcast's downloads, newest episode first.
+    let mut newest: Vec<&podcast::DownloadedFile> = downloads.iter().collect();
+    newest.sort_by_key(|f| {
+        std::cmp::Reverse(f.episode.pub_date.as_deref().and_then(feed::parse_date))
+    });
+    let mut seen = std::collections::HashMap::new();
+    let mut ranks = std::collections::HashMap::new();
+    for file in newest {
+        let rank = seen.entry(file.podcast_id).or_insert(0);
+        *rank += 1;
+        ranks.insert(file.episode.id, *rank);
+    }
+
+    let mut doomed = Vec::new();
+    let mut kept = Vec::new();
+    for file in downloads {
+        let policy = &policies[&file.podcast_id];
+        let age = chrono::NaiveDateTime::parse_from_str(&file.downloaded_at, "%Y-%m-%d %H:%M:%S")
+            .map(|at| (now - at).num_days())
+            .unwrap_or(0);
+        let reason = if file.episode.starred {
+            None
+        } else if policy.delete_played && file.episode.played {
+            Some("played".to_string())
+        } else if policy
+            .keep_last
+            .is_some_and(|n| ranks[&file.episode.id] > n)
+        {
+            Some(format!(
+                "beyond the last {}",
+                policy.keep_last.unwrap_or_default()
+            ))
+        } else if policy.max_age_days.is_some_and(|days| age > days) {
+            Some(format!("downloaded {} days ago", age))
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => doomed.push((file, reason)),
+            None => kept.push(file),
+        }
+    }
+
+    if let Some(quota) = quota {
+        let mut used: u64 = kept.iter().map(|f| f.size).sum();
+        // Stable sorts, so each group stays oldest download first.
+        kept.sort_by_key(|f| !f.episode.played);
+        for file in kept {
+            if used <= quota {
+                break;
+            }
+            if file.episode.starred {
+                continue;
+            }
+            used -= file.size;
+            doomed.push((file, "over the disk quota".to_string()));
+        }
+    }
+    Ok(doomed)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1e6)
+}
+
 pub struct Remove {
     pub id: i64,
 }
 
-// impl Action for Remove {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
-//
-// pub struct Download {
-//     pub id: i64,
-// }
-//
-// impl Action for Download {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
-//
-// pub struct Search {
-//     pub term: String,
-//     pub detailed: bool,
-// }
-//
-// impl Action for Search {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
-//
-// pub struct Import {
-//     pub file: String,
-// }
-//
-// impl Action for Import {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
-//
-// pub struct Export {
-//     pub file: String,
-// }
-//
-// impl Action for Export {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
-//
-// pub struct Play {
-//     pub id: i64,
-// }
+impl Action for Remove {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let pod = db::fetch_podcast(&conn, self.id).or_not_found(|| no_podcast(self.id))?;
+        db::remove_podcast(&conn, self.id)?;
+        tx.send(ActionEvent::Finished(format!("Removed {}.", pod.title)))?;
+        Ok(())
+    }
+}
+
+pub struct Download {
+    pub id: i64,
+    pub dir: PathBuf,
+    pub template: template::Template,
+    // Tag the file once it's down.
+    pub tag: bool,
+}
+
+impl Action for Download {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let ep = db::fetch_episode(&conn, self.id).or_not_found(|| no_episode(self.id))?;
+        let pod = db::fetch_podcast_of_episode(&conn, self.id)?;
+        let dl = download_episode(&tx, &conn, &self.dir, &self.template, self.tag, &pod, &ep)?;
+        tx.send(ActionEvent::Finished(format!(
+            "Downloaded {} to {}.",
+            ep.title,
+            dl.path.display()
+        )))?;
+        Ok(())
+    }
+}
+
+pub struct Search {
+    pub term: String,
+    pub detailed: bool,
+    // Search episodes rather than podcasts.
+    pub episodes: bool,
+    // Only search this podcast's episodes.
+    pub id: Option<i64>,
+    // What goes either side of each match in the snippets.
+    pub highlight: (&'static str, &'static str),
+}
+
+impl Search {
+    pub fn search_podcasts(
+        &self,
+        conn: &rusqlite::Connection,
+    ) -> Result<Vec<podcast::SearchHit<podcast::Podcast>>> {
+        db::search_podcasts(conn, &self.term, self.highlight).map_err(search_error)
+    }
+
+    pub fn search_episodes(
+        &self,
+        conn: &rusqlite::Connection,
+    ) -> Result<Vec<podcast::SearchHit<podcast::Episode>>> {
+        db::search_episodes(conn, &self.term, self.id, self.highlight).map_err(search_error)
+    }
+
+    // Searching within a podcast can only mean its episodes.
+    pub fn wants_episodes(&self) -> bool {
+        self.episodes || self.id.is_some()
+    }
+}
+
+// SQLite reports mistakes in the query as a plain SQLITE_ERROR, and explains them well
+// enough. Anything else is the database's fault.
+fn search_error(e: rusqlite::Error) -> anyhow::Error {
+    let message = format!("Search failed: {}", e);
+    match e.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::Unknown) => Error::Parse(message).into(),
+        _ => Error::Database(message).into(),
+    }
+}
+
+impl Action for Search {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let hits: Vec<(String, String)> = if self.wants_episodes() {
+            self.search_episodes(&conn)?
+                .into_iter()
+                .map(|hit| (hit.item.str(self.detailed), hit.snippet))
+                .collect()
+        } else {
+            self.search_podcasts(&conn)?
+                .into_iter()
+                .map(|hit| (hit.item.str(self.detailed), hit.snippet))
+                .collect()
+        };
+        for (item, snippet) in hits {
+            tx.send(ActionEvent::Output(item))?;
+            tx.send(ActionEvent::Output(format!(
+                "    {}",
+                snippet.split_whitespace().collect::<Vec<_>>().join(" ")
+            )))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Import {
+    pub file: String,
+    // How many feeds are fetched at once.
+    pub jobs: usize,
+    // Only say what would be added.
+    pub dry_run: bool,
+}
+
+// A feed listed in an OPML file. The folders it was filed under become its tags, and the
+// first of them its category unless the outline names categories of its own. The policy is
+// only there when an extended export carried one.
+struct OpmlFeed {
+    url: String,
+    title: String,
+    categories: Vec<String>,
+    tags: Vec<String>,
+    policy: Option<podcast::Policy>,
+}
+
+// What became of the feeds in an OPML file, by title.
+#[derive(Default)]
+struct ImportReport {
+    added: Vec<String>,
+    present: Vec<String>,
+    failed: Vec<(String, anyhow::Error)>,
+}
+
+impl Action for Import {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let contents = std::fs::read_to_string(&self.file).map_err(|e| {
+            let message = format!("Can't read {}: {}", self.file, e);
+            match e.kind() {
+                std::io::ErrorKind::NotFound => Error::NotFound(message).into(),
+                _ => anyhow!(message),
+            }
+        })?;
+        let mut opml = OPML::from_str(&contents)
+            .map_err(|e| Error::Parse(format!("Can't read {} as OPML: {}", self.file, e)))?;
+        let settings = take_settings(&mut opml.body.outlines);
+        let mut feeds = Vec::new();
+        collect_feeds(&opml.body.outlines, &[], &mut feeds);
+
+        let mut report = ImportReport::default();
+        let mut pending = VecDeque::new();
+        for mut feed in merge_duplicates(feeds) {
+            if let Some(lines) = settings.get(&feed.url) {
+                read_settings(lines, &mut feed);
+            }
+            if let Some(id) = db::fetch_podcast_id_by_url(&conn, &feed.url)? {
+                // Tags still come across, so a podcast filed in folders elsewhere gets
+                // filed the same way here.
+                if !self.dry_run {
+                    restore_settings(&conn, id, &feed)?;
+                }
+                report.present.push(feed.title);
+            } else if self.dry_run {
+                tx.send(ActionEvent::Output(if feed.tags.is_empty() {
+                    format!("Would add {} ({}).", feed.title, feed.url)
+                } else {
+                    format!(
+                        "Would add {} ({}) tagged {}.",
+                        feed.title,
+                        feed.url,
+                        feed.tags.join(", ")
+                    )
+                }))?;
+                report.added.push(feed.title);
+            } else {
+                pending.push_back(feed);
+            }
+        }
+        self.fetch_all(&tx, &conn, pending, &mut report)?;
+
+        tx.send(ActionEvent::Finished(report.lines(self.dry_run).join("\n")))?;
+        match report.failed.first() {
+            Some((_, first)) => {
+                let message = format!("{} feeds failed to import.", report.failed.len());
+                Err(match Error::classify(first) {
+                    Some(kind) => kind.reworded(message).into(),
+                    None => anyhow!(message),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Import {
+    // Fetches the new feeds a few at a time and adds each as it comes in. One bad feed
+    // doesn't stop the rest.
+    fn fetch_all(
+        &self,
+        tx: &mpsc::Sender<ActionEvent>,
+        conn: &rusqlite::Connection,
+        pending: VecDeque<OpmlFeed>,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let client = reqwest::blocking::Client::new();
+        let pending = Mutex::new(pending);
+        std::thread::scope(|scope| -> Result<()> {
+            let (results_tx, results_rx) = mpsc::channel();
+            for _ in 0..self.jobs.max(1) {
+                let (results_tx, tx) = (results_tx.clone(), tx.clone());
+                let (pending, client) = (&pending, &client);
+                scope.spawn(move || loop {
+                    let feed = match pending.lock().unwrap().pop_front() {
+                        Some(feed) => feed,
+                        None => break,
+                    };
+                    let _ = tx.send(ActionEvent::Started(format!("Fetching {}...", feed.url)));
+                    let fetched = fetch_new(client, &feed.url);
+                    if results_tx.send((feed, fetched)).is_err() {
+                        break;
+                    }
+                });
+            }
+            // The workers hold the only senders left, so this ends once they're all done.
+            drop(results_tx);
+            for (feed, fetched) in results_rx {
+                let (mut pod, cache) = match fetched {
+                    Ok(fetched) => fetched,
+                    Err(e) => {
+                        report.failed.push((feed.title, e));
+                        continue;
+                    }
+                };
+                for category in &feed.categories {
+                    if !pod.categories.contains(category) {
+                        pod.categories.push(category.clone());
+                    }
+                }
+                store_new(conn, &mut pod, &cache)?;
+                restore_settings(conn, pod.id, &feed)?;
+                report.added.push(pod.title);
+            }
+            Ok(())
+        })
+    }
+}
+
+// Gathers the feeds from an outline tree. Outlines without a feed are folders, and the
+// feeds inside them get the folder names, joined with slashes like iTunes subcategories,
+// as a tag and their category.
+fn collect_feeds(outlines: &[opml::Outline], folders: &[String], feeds: &mut Vec<OpmlFeed>) {
+    for outline in outlines {
+        let name = match outline.text.trim() {
+            "" => outline.title.clone().unwrap_or_default(),
+            text => text.to_string(),
+        };
+        match &outline.xml_url {
+            Some(url) => {
+                let folder = podcast::clean_tag(&folders.join("/"));
+                let mut feed = OpmlFeed {
+                    url: url.clone(),
+                    title: if name.is_empty() { url.clone() } else { name },
+                    categories: folder.iter().cloned().collect(),
+                    tags: folder.into_iter().collect(),
+                    policy: None,
+                };
+                if let Some(categories) = &outline.category {
+                    read_categories(categories, &mut feed);
+                }
+                feeds.push(feed);
+            }
+            None => {
+                let mut folders = folders.to_vec();
+                if !name.is_empty() {
+                    folders.push(name);
+                }
+                collect_feeds(&outline.outlines, &folders, feeds);
+            }
+        }
+    }
+}
+
+// The values of an outline's category attribute, which replace the folder it was in.
+fn read_categories(categories: &str, feed: &mut OpmlFeed) {
+    feed.categories.clear();
+    for value in categories.split(',') {
+        if let Some(category) = podcast::clean_tag(value) {
+            if !feed.categories.contains(&category) {
+                feed.categories.push(category);
+            }
+        }
+    }
+}
+
+// Takes the settings outline of an extended export out of the body, so its podcasts aren't
+// mistaken for folders, and returns each podcast's settings by feed URL.
+fn take_settings(outlines: &mut Vec<opml::Outline>) -> HashMap<String, Vec<String>> {
+    let mut settings = HashMap::new();
+    outlines.retain(|outline| {
+        if outline.is_comment != Some(true) || outline.text != SETTINGS_OUTLINE {
+            return true;
+        }
+        for pod in &outline.outlines {
+            if let Some(url) = &pod.url {
+                let lines = pod.outlines.iter().map(|o| o.text.clone()).collect();
+                settings.insert(url.clone(), lines);
+            }
+        }
+        false
+    });
+    settings
+}
+
+// Gives a feed the tags and policy from its "name=value" lines in the settings outline.
+// Settings this dipper doesn't know, say from a newer one, are skipped.
+fn read_settings(lines: &[String], feed: &mut OpmlFeed) {
+    let policy = feed.policy.get_or_insert_with(Default::default);
+    for line in lines {
+        let (name, value) = line.split_once('=').unwrap_or((line, ""));
+        let value = value.trim();
+        match name.trim() {
+            "tag" => {
+                if let Some(tag) = podcast::clean_tag(value) {
+                    add_tag(&mut feed.tags, tag);
+                }
+            }
+            "auto-download" => policy.auto_download = value == "on",
+            "keep-last" => policy.keep_last = value.parse().ok(),
+            "max-age-days" => policy.max_age_days = value.parse().ok(),
+            "delete-played" => policy.delete_played = value == "on",
+            "speed" => policy.speed = value.parse().ok(),
+            _ => (),
+        }
+    }
+}
+
+// Tags match case-insensitively, so "News" is already there if "news" is.
+fn add_tag(tags: &mut Vec<String>, tag: String) {
+    if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+        tags.push(tag);
+    }
+}
+
+// Tags a podcast from an OPML file, and gives it the policy the file carried, if any. A file
+// that carries settings is an extended export, whose tags are the podcast's whole set, so
+// they replace the ones it has rather than adding to them.
+fn restore_settings(conn: &rusqlite::Connection, id: i64, feed: &OpmlFeed) -> Result<()> {
+    match &feed.policy {
+        Some(policy) => {
+            db::set_tags(conn, id, &feed.tags)?;
+            db::save_policy(conn, id, policy)?;
+        }
+        None => {
+            for tag in &feed.tags {
+                db::tag_podcast(conn, id, tag)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The same feed filed in several folders only comes in once, where it first appears, but
+// with every folder as a tag.
+fn merge_duplicates(feeds: Vec<OpmlFeed>) -> Vec<OpmlFeed> {
+    let mut merged: Vec<OpmlFeed> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for feed in feeds {
+        match seen.get(&feed.url) {
+            Some(&i) => {
+                for tag in feed.tags {
+                    add_tag(&mut merged[i].tags, tag);
+                }
+            }
+            None => {
+                seen.insert(feed.url.clone(), merged.len());
+                merged.push(feed);
+            }
+        }
+    }
+    merged
+}
+
+impl ImportReport {
+    fn lines(&self, dry_run: bool) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} {} podcasts, {} already here, {} failed.",
+            if dry_run { "Would add" } else { "Added" },
+            self.added.len(),
+            self.present.len(),
+            self.failed.len()
+        )];
+        if !dry_run {
+            for title in &self.added {
+                lines.push(format!("  Added {}", title));
+            }
+        }
+        for title in &self.present {
+            lines.push(format!("  Already here: {}", title));
+        }
+        for (title, e) in &self.failed {
+            lines.push(format!("  Failed {}: {}", title, e));
+        }
+        lines
+    }
+}
+
+pub struct Export {
+    pub file: String,
+    // Who the subscriptions belong to, for the OPML head.
+    pub owner_name: Option<String>,
+    // Include dipper's own settings for each podcast, so importing the file restores them.
+    pub extended: bool,
+}
+
+// OPML has no room for attributes of our own, so extended exports add one last outline
+// holding each podcast's dipper settings, marked as a comment for other podcatchers to
+// skip. Under it, an outline per podcast names the feed by its URL and has a "name=value"
+// outline for each setting:
 //
-// impl Action for Play {
-//     fn execute(&self, conn: rusqlite::Connection) -> Result<String> {
-//         todo!()
-//     }
-// }
+//   <outline text="dipper" isComment="true">
+//     <outline text="The Show" url="https://example.com/feed.xml">
+//       <outline text="auto-download=on"/>
+//       <outline text="keep-last=10"/>
+//       <outline text="max-age-days=30"/>
+//       <outline text="delete-played=off"/>
+//       <outline text="speed=1.5"/>
+//       <outline text="tag=News/Tech"/>
 //
-// // pub struct Pause {}
-// // pub struct FastForward {}
-// // pub struct Rewind {}
-// // pub struct SkipNext {}
-// // pub struct SkipPrev {}
+// A podcatcher that ignores isComment finds only folders with no feeds in them there.
+const SETTINGS_OUTLINE: &str = "dipper";
+
+fn settings_outline(pod: &podcast::Podcast, policy: &podcast::Policy) -> opml::Outline {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let mut lines = vec![format!("auto-download={}", on_off(policy.auto_download))];
+    if let Some(n) = policy.keep_last {
+        lines.push(format!("keep-last={}", n));
+    }
+    if let Some(days) = policy.max_age_days {
+        lines.push(format!("max-age-days={}", days));
+    }
+    lines.push(format!("delete-played={}", on_off(policy.delete_played)));
+    if let Some(speed) = policy.speed {
+        lines.push(format!("speed={}", speed));
+    }
+    for tag in &pod.tags {
+        lines.push(format!("tag={}", tag));
+    }
+    opml::Outline {
+        text: pod.title.clone(),
+        url: Some(pod.rss_url.clone()),
+        outlines: lines
+            .into_iter()
+            .map(|text| opml::Outline {
+                text,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+// A podcast's categories, as its outline's category attribute.
+fn export_categories(pod: &podcast::Podcast) -> Option<String> {
+    let values: Vec<String> = pod.categories.iter().map(|c| format!("/{}", c)).collect();
+    Some(values.join(",")).filter(|v| !v.is_empty())
+}
+
+impl Action for Export {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let mut pods = db::fetch_all_podcasts(&conn)?;
+        // The publisher asked for locked feeds not to be moved to other platforms.
+        for pod in pods.iter().filter(|p| p.locked) {
+            tx.send(ActionEvent::Warning(format!(
+                "Skipping {}: the feed is locked by its publisher.",
+                pod.title
+            )))?;
+        }
+        pods.retain(|p| !p.locked);
+        // Tags become folders, nested where the tag is, so other podcatchers file the
+        // podcasts the same way. A podcast with several tags is in each of their folders.
+        let mut outlines = Vec::new();
+        let mut settings = Vec::new();
+        for pod in &pods {
+            if self.extended {
+                settings.push(settings_outline(pod, &db::fetch_policy(&conn, pod.id)?));
+            }
+            let outline = opml::Outline {
+                text: pod.title.clone(),
+                r#type: Some("rss".to_string()),
+                title: Some(pod.title.clone()),
+                html_url: pod.link.clone(),
+                xml_url: Some(pod.rss_url.clone()),
+                category: export_categories(pod),
+                ..Default::default()
+            };
+            if pod.tags.is_empty() {
+                outlines.push(outline);
+            } else {
+                for tag in &pod.tags {
+                    folder(&mut outlines, tag).push(outline.clone());
+                }
+            }
+        }
+        if self.extended {
+            outlines.push(opml::Outline {
+                text: SETTINGS_OUTLINE.to_string(),
+                is_comment: Some(true),
+                outlines: settings,
+                ..Default::default()
+            });
+        }
+        let opml = OPML {
+            version: "2.0".to_string(),
+            head: Some(opml::Head {
+                title: Some("dipper subscriptions".to_string()),
+                date_created: Some(chrono::Utc::now().to_rfc2822()),
+                owner_name: self.owner_name.clone(),
+                ..Default::default()
+            }),
+            body: opml::Body { outlines },
+        };
+        let xml = opml
+            .to_string()
+            .map_err(|e| anyhow!("Can't write OPML: {}", e))?;
+        std::fs::write(&self.file, xml).map_err(|e| anyhow!("Can't write {}: {}", self.file, e))?;
+        tx.send(ActionEvent::Finished(format!(
+            "Exported {} podcasts to {}.",
+            pods.len(),
+            self.file
+        )))?;
+        Ok(())
+    }
+}
+
+// The outlines inside the folder for a tag like "News/Tech", made as needed. Folder names
+// match case-insensitively, like tags.
+fn folder<'a>(outlines: &'a mut Vec<opml::Outline>, tag: &str) -> &'a mut Vec<opml::Outline> {
+    let mut outlines = outlines;
+    for name in tag.split('/') {
+        let i = match outlines
+            .iter()
+            .position(|o| o.xml_url.is_none() && o.text.eq_ignore_ascii_case(name))
+        {
+            Some(i) => i,
+            None => {
+                outlines.push(opml::Outline {
+                    text: name.to_string(),
+                    title: Some(name.to_string()),
+                    ..Default::default()
+                });
+                outlines.len() - 1
+            }
+        };
+        outlines = &mut outlines[i].outlines;
+    }
+    outlines
+}
+
+pub struct Backup {
+    pub file: PathBuf,
+}
+
+impl Action for Backup {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let backup = backup::collect(&conn)?;
+        backup::write(&self.file, &backup)?;
+        let episodes: usize = backup.podcasts.iter().map(|p| p.episodes.len()).sum();
+        tx.send(ActionEvent::Finished(format!(
+            "Backed up {} podcasts and {} episodes to {}.",
+            backup.podcasts.len(),
+            episodes,
+            self.file.display()
+        )))?;
+        Ok(())
+    }
+}
+
+pub struct Restore {
+    pub file: PathBuf,
+}
+
+impl Action for Restore {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let backup = backup::read(&self.file)?;
+        tx.send(ActionEvent::Started(format!(
+            "Restoring {} podcasts from a backup made {}...",
+            backup.podcasts.len(),
+            backup.created
+        )))?;
+        let report = backup::restore(&conn, &backup)?;
+        tx.send(ActionEvent::Finished(report.lines().join("\n")))?;
+        Ok(())
+    }
+}
+
+pub struct Play {
+    pub id: i64,
+    // A command to play with instead of the built-in player.
+    pub player: Option<String>,
+}
+
+impl Action for Play {
+    fn execute(&self, tx: mpsc::Sender<ActionEvent>, conn: rusqlite::Connection) -> Result<()> {
+        let ep = db::fetch_episode(&conn, self.id).or_not_found(|| no_episode(self.id))?;
+        tx.send(ActionEvent::Started(format!("Playing {}...", ep.title)))?;
+        match &self.player {
+            Some(command) => player::play_with(command, &ep)?,
+            None => player::play(&conn, &ep)?,
+        }
+        tx.send(ActionEvent::Finished(format!("Stopped {}.", ep.title)))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+use crate::db;
+use crate::error::Error;
+use crate::podcast;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+// A backup is a gzipped tarball holding a single JSON file, `dipper-backup.json`, with the
+// whole library: podcasts and their episodes, what's been played and how far, the queue,
+// tags, policies and settings. Things the feeds give back on the next update, like
+// chapters and transcripts, are left out, as are downloads, which belong to one machine.
+//
+// Nothing refers to row ids. Podcasts are known by their feed URL and episodes by their
+// guid, so a backup can be restored into a database that already has some of it.
+//
+// The layout is versioned. Fields may be added within a version; anything else gets a new
+// one, and a dipper refuses backups newer than it knows.
+pub const VERSION: u32 = 1;
+
+// What the JSON says it is, so it can be told apart from any other JSON.
+const FORMAT: &str = "dipper-backup";
+
+const FILE_NAME: &str = "dipper-backup.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    pub format: String,
+    pub version: u32,
+    // When the backup was made, in RFC 3339.
+    pub created: String,
+    // The dipper that made it.
+    pub dipper_version: String,
+    pub podcasts: Vec<PodcastBackup>,
+    // The Up Next queue, in order.
+    pub queue: Vec<EpisodeRef>,
+    pub settings: BTreeMap<String, String>,
+}
+
+// Just enough to read before deciding whether the rest can be read.
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PodcastBackup {
+    pub rss_url: String,
+    pub title: String,
+    pub description: String,
+    pub link: Option<String>,
+    pub language: Option<String>,
+    pub pub_date: Option<String>,
+    pub last_build_date: Option<String>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub summary: Option<String>,
+    pub explicit: Option<bool>,
+    pub categories: Vec<String>,
+    pub block: bool,
+    pub locked: bool,
+    pub tags: Vec<String>,
+    pub funding: Vec<FundingBackup>,
+    pub policy: PolicyBackup,
+    pub episodes: Vec<EpisodeBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FundingBackup {
+    pub url: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicyBackup {
+    pub auto_download: bool,
+    pub keep_last: Option<i64>,
+    pub max_age_days: Option<i64>,
+    pub delete_played: bool,
+    pub speed: Option<f64>,
+}
+
+// An episode and where its listener got to. Times are in seconds; `last_played` is
+// "YYYY-MM-DD HH:MM:SS" in UTC.
+#[derive(Serialize, Deserialize)]
+pub struct EpisodeBackup {
+    pub guid: String,
+    pub title: String,
+    pub description: String,
+    pub pub_date: Option<String>,
+    pub link: Option<String>,
+    pub enclosure_url: Option<String>,
+    pub enclosure_length: Option<String>,
+    pub enclosure_mime_type: Option<String>,
+    pub duration: Option<i64>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub summary: Option<String>,
+    pub explicit: Option<bool>,
+    pub episode_number: Option<i64>,
+    pub season: Option<i64>,
+    pub episode_type: Option<String>,
+    pub block: bool,
+    pub season_name: Option<String>,
+    pub episode_display: Option<String>,
+    pub removed: bool,
+    pub played: bool,
+    pub position: i64,
+    pub last_played: Option<String>,
+    pub starred: bool,
+}
+
+// An episode, by its podcast's feed and its guid.
+#[derive(Serialize, Deserialize)]
+pub struct EpisodeRef {
+    pub rss_url: String,
+    pub guid: String,
+}
+
+// What restoring a backup changed.
+#[derive(Default)]
+pub struct RestoreReport {
+    pub podcasts_added: usize,
+    pub podcasts_merged: usize,
+    pub episodes_added: usize,
+    // Episodes we already had whose listening state the backup moved on.
+    pub episodes_updated: usize,
+    pub queued: usize,
+    pub settings: usize,
+}
+
+impl RestoreReport {
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!(
+                "Restored {} new podcasts and merged {} already here.",
+                self.podcasts_added, self.podcasts_merged
+            ),
+            format!(
+                "  {} episodes added, {} brought up to date.",
+                self.episodes_added, self.episodes_updated
+            ),
+            format!(
+                "  {} episodes queued, {} settings restored.",
+                self.queued, self.settings
+            ),
+        ]
+    }
+}
+
+// Gathers the whole library up into a backup.
+pub fn collect(conn: &rusqlite::Connection) -> Result<Backup> {
+    let mut podcasts = Vec::new();
+    for pod in db::fetch_all_podcasts(conn)? {
+        // Only a podcast fetched on its own comes with its funding links.
+        let mut pod = db::fetch_podcast(conn, pod.id)?;
+        pod.episodes = db::fetch_episodes(conn, pod.id)?;
+        let policy = db::fetch_policy(conn, pod.id)?;
+        podcasts.push(PodcastBackup::new(&pod, &policy));
+    }
+    let mut queue = Vec::new();
+    for ep in db::fetch_queue(conn)? {
+        let pod = db::fetch_podcast_of_episode(conn, ep.id)?;
+        queue.push(EpisodeRef {
+            rss_url: pod.rss_url,
+            guid: ep.guid,
+        });
+    }
+    Ok(Backup {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        created: chrono::Utc::now().to_rfc3339(),
+        dipper_version: env!("CARGO_PKG_VERSION").to_string(),
+        podcasts,
+        queue,
+        settings: db::fetch_settings(conn)?.into_iter().collect(),
+    })
+}
+
+pub fn write(path: &Path, backup: &Backup) -> Result<()> {
+    let json = serde_json::to_vec_pretty(backup)?;
+    let write = || -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+        tar.append_data(&mut header, FILE_NAME, json.as_slice())?;
+        tar.into_inner()?.finish()?;
+        Ok(())
+    };
+    write().map_err(|e| anyhow!("Can't write {}: {}", path.display(), e))
+}
+
+pub fn read(path: &Path) -> Result<Backup> {
+    let file = std::fs::File::open(path).map_err(|e| {
+        let message = format!("Can't read {}: {}", path.display(), e);
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Error::NotFound(message).into(),
+            _ => anyhow!(message),
+        }
+    })?;
+    let not_a_backup = |why: String| -> anyhow::Error {
+        Error::Parse(format!("{} isn't a dipper backup: {}", path.display(), why)).into()
+    };
+    let json = find_json(file)
+        .map_err(|e| not_a_backup(e.to_string()))?
+        .ok_or_else(|| not_a_backup(format!("there's no {} in it.", FILE_NAME)))?;
+    let header: Header = serde_json::from_str(&json).map_err(|e| not_a_backup(e.to_string()))?;
+    if header.format != FORMAT {
+        return Err(not_a_backup(format!("it says it's {:?}.", header.format)));
+    }
+    if header.version > VERSION {
+        return Err(Error::Parse(format!(
+            "{} is a version {} backup, but this dipper only knows up to version {}.",
+            path.display(),
+            header.version,
+            VERSION
+        ))
+        .into());
+    }
+    serde_json::from_str(&json).map_err(|e| not_a_backup(e.to_string()))
+}
+
+fn find_json(file: std::fs::File) -> std::io::Result<Option<String>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_os_str() == FILE_NAME {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            return Ok(Some(json));
+        }
+    }
+    Ok(None)
+}
+
+// Merges a backup into the database, all or nothing. Podcasts, episodes, tags and queue
+// entries we don't have are added. For episodes we do have, listening state from the
+// backup wins only where it's newer. Policies and settings already here are left alone.
+pub fn restore(conn: &rusqlite::Connection, backup: &Backup) -> Result<RestoreReport> {
+    let tx = conn.unchecked_transaction()?;
+    let mut report = RestoreReport::default();
+    // Episode ids by feed and guid, for putting the queue back together.
+    let mut ids = HashMap::new();
+    for pod in &backup.podcasts {
+        let id = match db::fetch_podcast_id_by_url(&tx, &pod.rss_url)? {
+            Some(id) => {
+                report.podcasts_merged += 1;
+                id
+            }
+            None => {
+                let mut new = pod.to_podcast();
+                db::insert_podcast(&tx, &mut new)?;
+                db::save_policy(&tx, new.id, &pod.policy.to_policy())?;
+                report.podcasts_added += 1;
+                new.id
+            }
+        };
+        for tag in &pod.tags {
+            db::tag_podcast(&tx, id, tag)?;
+        }
+        let local: HashMap<String, podcast::Episode> = db::fetch_episodes(&tx, id)?
+            .into_iter()
+            .map(|ep| (ep.guid.clone(), ep))
+            .collect();
+        for ep in &pod.episodes {
+            let episode = ep.to_episode();
+            let episode_id = match local.get(&ep.guid) {
+                Some(local) => {
+                    if let Some(merged) = merge_state(local, &episode) {
+                        db::save_playback_state(&tx, local.id, &merged)?;
+                        report.episodes_updated += 1;
+                    }
+                    local.id
+                }
+                None => match db::upsert_episode(&tx, &episode, id)? {
+                    db::Upsert::Inserted(new_id) => {
+                        db::save_playback_state(&tx, new_id, &episode)?;
+                        report.episodes_added += 1;
+                        new_id
+                    }
+                    // We have its enclosure under another guid.
+                    _ => continue,
+                },
+            };
+            ids.insert((pod.rss_url.as_str(), ep.guid.as_str()), episode_id);
+        }
+    }
+    let queued: HashSet<i64> = db::fetch_queue(&tx)?.iter().map(|ep| ep.id).collect();
+    for entry in &backup.queue {
+        if let Some(id) = ids.get(&(entry.rss_url.as_str(), entry.guid.as_str())) {
+            if !queued.contains(id) {
+                db::queue_add(&tx, *id)?;
+                report.queued += 1;
+            }
+        }
+    }
+    for (key, value) in &backup.settings {
+        if db::fetch_setting(&tx, key)?.is_none() {
+            db::save_setting(&tx, key, Some(value))?;
+            report.settings += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+// The listening state to keep for an episode we have, or None if the backup's is no
+// news. Whichever side played it last wins; a star on either side stays.
+fn merge_state(local: &podcast::Episode, backup: &podcast::Episode) -> Option<podcast::Episode> {
+    let mut merged = local.clone();
+    if backup.last_played > local.last_played {
+        merged.played = backup.played;
+        merged.position = backup.position;
+        merged.last_played = backup.last_played.clone();
+    } else if backup.last_played == local.last_played {
+        // Marked played without being listened to, say.
+        merged.played |= backup.played;
+    }
+    merged.starred |= backup.starred;
+    let changed = merged.played != local.played
+        || merged.position != local.position
+        || merged.last_played != local.last_played
+        || merged.starred != local.starred;
+    changed.then_some(merged)
+}
+
+impl PodcastBackup {
+    fn new(pod: &podcast::Podcast, policy: &podcast::Policy) -> PodcastBackup {
+        PodcastBackup {
+            rss_url: pod.rss_url.clone(),
+            title: pod.title.clone(),
+            description: pod.description.clone(),
+            link: pod.link.clone(),
+            language: pod.language.clone(),
+            pub_date: pod.pub_date.clone(),
+            last_build_date: pod.last_build_date.clone(),
+            author: pod.author.clone(),
+            image: pod.image.clone(),
+            summary: pod.summary.clone(),
+            explicit: pod.explicit,
+            categories: pod.categories.clone(),
+            block: pod.block,
+            locked: pod.locked,
+            tags: pod.tags.clone(),
+            funding: pod
+                .funding
+                .iter()
+                .map(|f| FundingBackup {
+                    url: f.url.clone(),
+                    text: f.text.clone(),
+                })
+                .collect(),
+            policy: PolicyBackup {
+                auto_download: policy.auto_download,
+                keep_last: policy.keep_last,
+                max_age_days: policy.max_age_days,
+                delete_played: policy.delete_played,
+                speed: policy.speed,
+            },
+            episodes: pod.episodes.iter().map(EpisodeBackup::new).collect(),
+        }
+    }
+
+    // The podcast, without its episodes, which are restored one by one.
+    fn to_podcast(&self) -> podcast::Podcast {
+        let mut pod = podcast::Podcast::new(
+            self.title.clone(),
+            self.description.clone(),
+            self.rss_url.clone(),
+        );
+        pod.link = self.link.clone();
+        pod.language = self.language.clone();
+        pod.pub_date = self.pub_date.clone();
+        pod.last_build_date = self.last_build_date.clone();
+        pod.author = self.author.clone();
+        pod.image = self.image.clone();
+        pod.summary = self.summary.clone();
+        pod.explicit = self.explicit;
+        pod.categories = self.categories.clone();
+        pod.block = self.block;
+        pod.locked = self.locked;
+        pod.funding = self
+            .funding
+            .iter()
+            .map(|f| podcast::Funding {
+                url: f.url.clone(),
+                text: f.text.clone(),
+            })
+            .collect();
+        pod
+    }
+}
+
+impl PolicyBackup {
+    fn to_policy(&self) -> podcast::Policy {
+        podcast::Policy {
+            auto_download: self.auto_download,
+            keep_last: self.keep_last,
+            max_age_days: self.max_age_days,
+            delete_played: self.delete_played,
+            speed: self.speed,
+        }
+    }
+}
+
+impl EpisodeBackup {
+    fn new(ep: &podcast::Episode) -> EpisodeBackup {
+        EpisodeBackup {
+            guid: ep.guid.clone(),
+            title: ep.title.clone(),
+            description: ep.description.clone(),
+            pub_date: ep.pub_date.clone(),
+            link: ep.link.clone(),
+            enclosure_url: ep.enclosure.as_ref().map(|e| e.url.clone()),
+            enclosure_length: ep.enclosure.as_ref().and_then(|e| e.length.clone()),
+            enclosure_mime_type: ep.enclosure.as_ref().and_then(|e| e.mime_type.clone()),
+            duration: ep.duration,
+            author: ep.author.clone(),
+            image: ep.image.clone(),
+            summary: ep.summary.clone(),
+            explicit: ep.explicit,
+            episode_number: ep.episode_number,
+            season: ep.season,
+            episode_type: ep.episode_type.clone(),
+            block: ep.block,
+            season_name: ep.season_name.clone(),
+            episode_display: ep.episode_display.clone(),
+            removed: ep.removed,
+            played: ep.played,
+            position: ep.position,
+            last_played: ep.last_played.clone(),
+            starred: ep.starred,
+        }
+    }
+
+    fn to_episode(&self) -> podcast::Episode {
+        let mut ep = podcast::Episode::new(
+            self.title.clone(),
+            self.guid.clone(),
+            self.description.clone(),
+        );
+        ep.pub_date = self.pub_date.clone();
+        ep.link = self.link.clone();
+        ep.enclosure = self.enclosure_url.as_ref().map(|url| podcast::Enclosure {
+            url: url.clone(),
+            length: self.enclosure_length.clone(),
+            mime_type: self.enclosure_mime_type.clone(),
+        });
+        ep.duration = self.duration;
+        ep.author = self.author.clone();
+        ep.image = self.image.clone();
+        ep.summary = self.summary.clone();
+        ep.explicit = self.explicit;
+        ep.episode_number = self.episode_number;
+        ep.season = self.season;
+        ep.episode_type = self.episode_type.clone();
+        ep.block = self.block;
+        ep.season_name = self.season_name.clone();
+        ep.episode_display = self.episode_display.clone();
+        ep.removed = self.removed;
+        ep.played = self.played;
+        ep.position = self.position;
+        ep.last_played = self.last_played.clone();
+        ep.starred = self.starred;
+        ep
+    }
+}
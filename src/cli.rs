@@ -1,19 +1,33 @@
 use crate::actions;
-use crate::actions::Action;
+use crate::actions::{Action, ActionEvent};
+use crate::config;
 use crate::db;
-use crate::feed;
+use crate::error::{no_episode, no_podcast, Error, OrNotFound};
+use crate::output::{self, Format};
+use crate::podcast;
+use crate::template;
 use crate::tui;
-use clap::{Parser, Subcommand};
-use opml::OPML;
-
-const DEFAULT_DB_NAME: &str = "test.db";
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use std::io::IsTerminal;
 
 #[derive(Parser)]
-#[command(author, about, version, long_about = None)]
+#[command(author, about, version, long_about = None, after_help = crate::error::EXIT_CODES)]
 struct Cli {
     // The database to operate on.
+    #[arg(env = "DIPPER_DB")]
     db: Option<String>,
 
+    // How to print results: plain, table, json or ndjson.
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "DIPPER_FORMAT",
+        default_value = "plain"
+    )]
+    format: Format,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,6 +47,14 @@ enum Commands {
         // Number of podcasts to show.
         #[arg(short, long)]
         limit: Option<i64>,
+
+        // Only show podcasts in this iTunes category.
+        #[arg(short, long)]
+        category: Option<String>,
+
+        // Only show podcasts with this tag.
+        #[arg(long)]
+        tagged: Option<String>,
     },
     // Add a podcast.
     Add {
@@ -42,8 +64,12 @@ enum Commands {
     // List episodes.
     Episodes {
         // The id of the podcast to show episodes for.
-        #[arg(short, long)]
-        id: i64,
+        #[arg(short, long, required_unless_present = "tagged")]
+        id: Option<i64>,
+
+        // Show the episodes of every podcast with this tag instead.
+        #[arg(long, conflicts_with = "id")]
+        tagged: Option<String>,
 
         // Detailed output.
         #[arg(short, long)]
@@ -52,12 +78,36 @@ enum Commands {
         // Number of episodes to show.
         #[arg(short, long)]
         limit: Option<i64>,
+
+        // Only show episodes from this season.
+        #[arg(short, long)]
+        season: Option<i64>,
+
+        // Only show episodes of this type (full, trailer or bonus).
+        #[arg(short = 't', long = "type")]
+        episode_type: Option<String>,
+
+        // Hide episodes marked explicit.
+        #[arg(long)]
+        no_explicit: bool,
     },
     // Update podcasts.
     Update {
         // The id of the podcast to update.
         #[arg(short, long)]
         id: Option<i64>,
+        // Only update the podcasts with this tag.
+        #[arg(long, conflicts_with = "id")]
+        tagged: Option<String>,
+        // How many feeds to fetch at once.
+        #[arg(short, long, env = "DIPPER_JOBS")]
+        jobs: Option<usize>,
+        // Seconds to wait on a feed before giving up on it.
+        #[arg(short, long, env = "DIPPER_TIMEOUT")]
+        timeout: Option<u64>,
+        // Where podcasts set to auto-download save their new episodes.
+        #[command(flatten)]
+        download: DownloadArgs,
     },
     // Remove a podcast.
     Remove {
@@ -67,6 +117,8 @@ enum Commands {
     Download {
         // The id of the episode to download.
         id: i64,
+        #[command(flatten)]
+        download: DownloadArgs,
     },
     Search {
         // Detailed output.
@@ -81,201 +133,799 @@ enum Commands {
         #[arg(short, long)]
         id: Option<i64>,
 
-        // The search term.
+        // What to look for, in SQLite FTS5 query syntax: words, "exact phrases",
+        // prefix*, AND/OR/NOT and column filters like title:rust.
         term: String,
     },
+    // Subscribe to the podcasts in an OPML file. Folders become tags and categories.
     Import {
         file: String,
+        // How many feeds to fetch at once.
+        #[arg(short, long, env = "DIPPER_JOBS")]
+        jobs: Option<usize>,
+        // Only show what would be added.
+        #[arg(long)]
+        dry_run: bool,
     },
+    // Write the podcasts to an OPML file, in folders by tag.
     Export {
         file: String,
+        // Also write dipper's own settings for each podcast, which `import` restores:
+        // auto-download and retention policies, playback speed and tags. They go in an
+        // outline of their own marked as a comment, which other podcatchers should skip.
+        #[arg(long)]
+        extended: bool,
+        // Who the subscriptions belong to.
+        #[arg(long, env = "DIPPER_OWNER_NAME")]
+        owner_name: Option<String>,
+    },
+    Tui {
+        // How many feeds background updates fetch at once.
+        #[arg(short, long, env = "DIPPER_JOBS")]
+        jobs: Option<usize>,
+        // Seconds background updates wait on a feed before giving up on it.
+        #[arg(short, long, env = "DIPPER_TIMEOUT")]
+        timeout: Option<u64>,
+        // Where podcasts set to auto-download save their new episodes.
+        #[command(flatten)]
+        download: DownloadArgs,
     },
-    Tui,
     Play {
         id: i64,
+        // A command to play with instead of the built-in player, e.g. "mpv --no-video".
+        // The episode's file or URL is added to the end, and "{start}" becomes the second to
+        // resume from (mpv gets --start on its own). How far it got isn't saved.
+        #[arg(long, env = "DIPPER_PLAYER")]
+        player: Option<String>,
+    },
+    // Mark an episode as played.
+    Played {
+        // The id of the episode.
+        id: i64,
+    },
+    // Mark an episode as unplayed.
+    Unplayed {
+        // The id of the episode.
+        id: i64,
+    },
+    // Manage the Up Next queue.
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommands,
+    },
+    // Star an episode so cleanup never deletes it.
+    Star {
+        // The id of the episode.
+        id: i64,
+    },
+    // Unstar an episode.
+    Unstar {
+        // The id of the episode.
+        id: i64,
+    },
+    // Save the whole library, listening history and all, to a backup file.
+    Backup {
+        file: std::path::PathBuf,
+    },
+    // Merge a backup file into the library. Podcasts are matched by feed URL and episodes
+    // by guid, and the more recent listening state of the two wins.
+    Restore {
+        file: std::path::PathBuf,
+    },
+    // Tag a podcast. Tags like "News/Tech" nest, and become folders in OPML.
+    Tag {
+        // The id of the podcast.
+        id: i64,
+
+        // The tags to add.
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    // Take tags off a podcast.
+    Untag {
+        // The id of the podcast.
+        id: i64,
+
+        // The tags to take off.
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    // List the tags in use, with how many podcasts have each.
+    Tags,
+    // Manage auto-download and retention policies.
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+    // Delete downloads as the retention policies and disk quota say.
+    Cleanup {
+        // Only show what would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+struct DownloadArgs {
+    // Where downloads go.
+    #[arg(long, env = "DIPPER_DOWNLOAD_DIR")]
+    dir: Option<std::path::PathBuf>,
+    // How to name downloaded files, e.g.
+    // "{podcast}/{date:%Y}/{episode_number:03} - {title}.{ext}".
+    #[arg(long, env = "DIPPER_FILENAME_TEMPLATE")]
+    template: Option<String>,
+    // Write the podcast's details and artwork into downloaded MP3 and M4A files.
+    #[arg(long, env = "DIPPER_TAG")]
+    tag: bool,
+}
+
+#[derive(Subcommand)]
+enum PolicyCommands {
+    // Show a podcast's policy.
+    Show {
+        // The id of the podcast.
+        id: i64,
     },
+    // Change a podcast's policy. Anything left out stays as it was.
+    Set {
+        // The id of the podcast.
+        id: i64,
+
+        // Download new episodes when updating.
+        #[arg(long)]
+        auto_download: Option<bool>,
+
+        // Only keep this many of the newest downloads, 0 for all of them.
+        #[arg(long)]
+        keep_last: Option<i64>,
+
+        // Delete downloads older than this many days, 0 to keep them forever.
+        #[arg(long)]
+        max_age_days: Option<i64>,
+
+        // Delete downloads once they've been played.
+        #[arg(long)]
+        delete_played: Option<bool>,
+
+        // Play at this speed, e.g. 1.5, or 0 for normal speed.
+        #[arg(long)]
+        speed: Option<f64>,
+    },
+    // Show or set how much space downloads may take up in all, e.g. 500M or 10G, or off.
+    Quota {
+        size: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueueCommands {
+    // Add an episode to the end of the queue.
+    Add {
+        // The id of the episode to queue.
+        id: i64,
+    },
+    // Show the queue.
+    List {
+        // Detailed output.
+        #[arg(short, long)]
+        detailed: bool,
+    },
+    // Take an episode off the queue.
+    Remove {
+        // The id of the episode to remove.
+        id: i64,
+    },
+    // Move an episode to a new place in the queue.
+    Move {
+        // The id of the episode to move.
+        id: i64,
+
+        // The new position, starting at 1.
+        position: usize,
+    },
+    // Empty the queue.
+    Clear,
+}
+
+// Download settings after falling back on the config file and defaults.
+struct DownloadSettings {
+    dir: std::path::PathBuf,
+    template: template::Template,
+    tag: bool,
+}
+
+impl DownloadArgs {
+    fn resolve(self, config: &config::Config) -> Result<DownloadSettings> {
+        let template = self
+            .template
+            .or_else(|| config.filename_template.clone())
+            .unwrap_or_else(|| template::DEFAULT_TEMPLATE.to_string());
+        Ok(DownloadSettings {
+            dir: self.dir.unwrap_or_else(|| config.download_dir()),
+            template: template::Template::parse(&template)
+                .map_err(|e| Error::Parse(e.to_string()))?,
+            tag: self.tag || config.tag.unwrap_or(false),
+        })
+    }
 }
 
+const DEFAULT_JOBS: usize = 4;
+const DEFAULT_TIMEOUT: u64 = 30;
+
 pub fn parse_args() {
     let cli = Cli::parse();
-    let db_name = cli.db.unwrap_or(DEFAULT_DB_NAME.to_string());
+    let format = cli.format;
+    if let Err(e) = run(cli) {
+        output::fail(format, e);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let format = cli.format;
+    let config = config::Config::load()?;
+    let db_name = match cli.db {
+        Some(db) => db,
+        None => {
+            let db = config.db();
+            if let Some(dir) = db.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| anyhow!("Can't create {}: {}", dir.display(), e))?;
+            }
+            db.to_string_lossy().into_owned()
+        }
+    };
     match cli.command {
         Commands::List {
             id,
             detailed,
             limit,
-        } => do_list(db_name, id, detailed, limit),
-        Commands::Add { url } => do_add(db_name, url),
+            category,
+            tagged,
+        } => do_list(db_name, format, id, detailed, limit, category, tagged),
+        Commands::Add { url } => do_add(db_name, format, url),
         Commands::Episodes {
             id,
+            tagged,
+            detailed,
+            limit,
+            season,
+            episode_type,
+            no_explicit,
+        } => do_episodes(
+            db_name,
+            format,
+            id,
+            tagged,
             detailed,
             limit,
-        } => do_episodes(db_name, id, detailed, limit),
-        Commands::Update { id } => do_update(db_name, id),
+            podcast::EpisodeFilter {
+                season,
+                episode_type,
+                hide_explicit: no_explicit,
+            },
+        ),
+        Commands::Update {
+            id,
+            tagged,
+            jobs,
+            timeout,
+            download,
+        } => do_update(
+            db_name,
+            format,
+            id,
+            tagged,
+            jobs.or(config.jobs).unwrap_or(DEFAULT_JOBS),
+            timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT),
+            download.resolve(&config)?,
+        ),
         Commands::Remove { id } => do_remove(db_name, id),
-        Commands::Download { id } => do_download(db_name, id),
+        Commands::Download { id, download } => do_download(db_name, id, download.resolve(&config)?),
         Commands::Search {
             term,
             detailed,
             episodes,
             id,
-        } => do_search(db_name, term, detailed, episodes, id),
-        Commands::Import { file } => do_import(db_name, file),
-        Commands::Export { file } => do_export(db_name, file),
-        Commands::Tui => tui::start().unwrap(),
-        Commands::Play { id } => do_play(db_name, id),
+        } => do_search(db_name, format, term, detailed, episodes, id),
+        Commands::Import {
+            file,
+            jobs,
+            dry_run,
+        } => do_import(
+            db_name,
+            file,
+            jobs.or(config.jobs).unwrap_or(DEFAULT_JOBS),
+            dry_run,
+        ),
+        Commands::Export {
+            file,
+            extended,
+            owner_name,
+        } => do_export(
+            db_name,
+            file,
+            extended,
+            owner_name.or(config.owner_name.clone()),
+        ),
+        Commands::Tui {
+            jobs,
+            timeout,
+            download,
+        } => do_tui(
+            db_name,
+            &config,
+            jobs.or(config.jobs).unwrap_or(DEFAULT_JOBS),
+            timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT),
+            download.resolve(&config)?,
+        ),
+        Commands::Play { id, player } => do_play(db_name, id, player.or(config.player.clone())),
+        Commands::Played { id } => do_set_played(db_name, id, true),
+        Commands::Unplayed { id } => do_set_played(db_name, id, false),
+        Commands::Queue { command } => do_queue(db_name, command),
+        Commands::Star { id } => do_set_starred(db_name, id, true),
+        Commands::Unstar { id } => do_set_starred(db_name, id, false),
+        Commands::Backup { file } => do_backup(db_name, file),
+        Commands::Restore { file } => do_restore(db_name, file),
+        Commands::Tag { id, tags } => do_tag(db_name, id, tags, true),
+        Commands::Untag { id, tags } => do_tag(db_name, id, tags, false),
+        Commands::Tags => do_tags(db_name),
+        Commands::Policy { command } => do_policy(db_name, command),
+        Commands::Cleanup { dry_run } => do_cleanup(db_name, dry_run),
+    }
+}
+
+// Runs an action, printing what it reports as it goes.
+fn run_action(act: &impl Action, conn: rusqlite::Connection) -> Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let printer = std::thread::spawn(move || print_events(rx));
+    let result = act.execute(tx, conn);
+    // The printer only stops early when stdout has gone, e.g. down a closed pipe, and
+    // there's no one left to tell about that.
+    let _ = printer.join();
+    result
+}
+
+// Results go to stdout; progress and problems go to stderr, out of the way of pipes.
+fn print_events(rx: std::sync::mpsc::Receiver<ActionEvent>) {
+    // Progress is redrawn in place, so the next line needs to start on a fresh one.
+    let mut mid_line = false;
+    for event in rx {
+        if mid_line && !matches!(event, ActionEvent::Progress { .. }) {
+            eprintln!();
+            mid_line = false;
+        }
+        match event {
+            ActionEvent::Started(what) => eprintln!("{}", what),
+            ActionEvent::Progress { done, total } => {
+                match total {
+                    Some(total) if total > 0 => eprint!(
+                        "\r{:.1} of {:.1} MB ({}%)",
+                        done as f64 / 1e6,
+                        total as f64 / 1e6,
+                        done * 100 / total
+                    ),
+                    _ => eprint!("\r{:.1} MB", done as f64 / 1e6),
+                }
+                mid_line = true;
+            }
+            ActionEvent::Output(line) | ActionEvent::Finished(line) => println!("{}", line),
+            ActionEvent::PodcastUpdated {
+                title,
+                new_episodes,
+            } => println!("Updated {} ({} new).", title, new_episodes),
+            ActionEvent::EpisodeAdded { id, title } => println!("  {} => {}", id, title),
+            ActionEvent::Warning(warning) => eprintln!("Note: {}", warning),
+            ActionEvent::Failed { error } => eprintln!("{}", error),
+        }
+    }
+    if mid_line {
+        eprintln!();
     }
 }
 
-fn do_list(db_name: String, id: Option<i64>, detailed: bool, limit: Option<i64>) {
-    let conn = db::init_db(&db_name).unwrap();
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
+fn do_list(
+    db_name: String,
+    format: Format,
+    id: Option<i64>,
+    detailed: bool,
+    limit: Option<i64>,
+    category: Option<String>,
+    tagged: Option<String>,
+) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
     let act = actions::List {
         id,
         detailed,
         limit,
+        category,
+        tagged,
     };
-    let thr = std::thread::spawn(move || {
-        while let Ok(out) = rx.recv() {
-            println!("{}", out);
-        }
-    });
-    act.execute(tx, conn).unwrap();
-    thr.join().unwrap();
+    if format == Format::Plain {
+        return run_action(&act, conn);
+    }
+    let pods = act.select(&conn)?;
+    let records: Vec<_> = pods.iter().map(output::PodcastRecord::from).collect();
+    output::print_list(format, &records);
+    Ok(())
 }
 
-fn do_add(db_name: String, url: String) {
-    let conn = db::init_db(&db_name).unwrap();
+fn do_add(db_name: String, format: Format, url: String) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
     let act = actions::Add { url };
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-    let thr = std::thread::spawn(move || {
-        while let Ok(out) = rx.recv() {
-            println!("{}", out);
-        }
-    });
-    act.execute(tx, conn).unwrap();
-    thr.join().unwrap();
+    if format == Format::Plain {
+        return run_action(&act, conn);
+    }
+    // Progress messages would only get in the way of the JSON or the table.
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let pod = act.run(&tx, &conn)?;
+    let record = output::AddRecord {
+        podcast: output::PodcastRecord::from(&pod),
+        episodes: pod.episodes.len(),
+    };
+    match format {
+        Format::Table => output::print_list(format, &[record]),
+        _ => output::print_record(format, &record),
+    }
+    Ok(())
 }
 
-fn do_episodes(db_name: String, id: i64, detailed: bool, limit: Option<i64>) {
-    let conn = db::init_db(&db_name).unwrap();
+fn do_episodes(
+    db_name: String,
+    format: Format,
+    id: Option<i64>,
+    tagged: Option<String>,
+    detailed: bool,
+    limit: Option<i64>,
+    filter: podcast::EpisodeFilter,
+) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
     let act = actions::Episodes {
         id,
+        tagged,
         detailed,
         limit,
+        filter,
     };
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-    let thr = std::thread::spawn(move || {
-        while let Ok(out) = rx.recv() {
-            println!("{}", out);
-        }
-    });
-    act.execute(tx, conn).unwrap();
-    thr.join().unwrap();
-}
-
-fn do_update(db_name: String, id: Option<i64>) {
-    let conn = db::init_db(&db_name).unwrap();
-    let act = actions::Update { id };
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-    let thr = std::thread::spawn(move || {
-        while let Ok(out) = rx.recv() {
-            println!("{}", out);
-        }
-    });
-    act.execute(tx, conn).unwrap();
-    thr.join().unwrap();
+    if format == Format::Plain {
+        return run_action(&act, conn);
+    }
+    let eps = act.select(&conn)?;
+    let records: Vec<_> = eps.iter().map(output::EpisodeRecord::from).collect();
+    output::print_list(format, &records);
+    Ok(())
 }
 
-fn do_remove(db_name: String, id: i64) {
-    let conn = db::init_db(&db_name).unwrap();
-    match db::fetch_podcast(&conn, id) {
-        Ok(pod) => {
-            db::remove_podcast(&conn, id).unwrap();
-            println!("Removed {}.", pod.title);
-        }
-        Err(_) => {
-            println!("No podcast with id {}.", id);
-        }
+fn do_update(
+    db_name: String,
+    format: Format,
+    id: Option<i64>,
+    tagged: Option<String>,
+    jobs: usize,
+    timeout: u64,
+    download: DownloadSettings,
+) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let act = actions::Update {
+        id,
+        tagged,
+        jobs,
+        timeout: std::time::Duration::from_secs(timeout),
+        download_dir: download.dir,
+        template: download.template,
+        tag: download.tag,
+    };
+    if format == Format::Plain {
+        return run_action(&act, conn);
+    }
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let summary = act.run(&tx, &conn)?;
+    let record = output::UpdateSummaryRecord::from(&summary);
+    match format {
+        Format::Json => output::print_record(format, &record),
+        _ => output::print_list(format, &record.podcasts),
+    }
+    // Whatever did get updated is printed above, but scripts still need to hear about
+    // the feeds that failed.
+    match summary.error() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn do_remove(db_name: String, id: i64) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    run_action(&actions::Remove { id }, conn)
+}
+
+fn do_download(db_name: String, id: i64, download: DownloadSettings) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let act = actions::Download {
+        id,
+        dir: download.dir,
+        template: download.template,
+        tag: download.tag,
+    };
+    run_action(&act, conn)
+}
+
+fn do_search(
+    db_name: String,
+    format: Format,
+    term: String,
+    detailed: bool,
+    episodes: bool,
+    id: Option<i64>,
+) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    // Bold the matches on a terminal, bracket them anywhere else.
+    let highlight = if format == Format::Plain && std::io::stdout().is_terminal() {
+        ("\x1b[1;33m", "\x1b[0m")
+    } else {
+        ("[", "]")
+    };
+    let act = actions::Search {
+        term,
+        detailed,
+        episodes,
+        id,
+        highlight,
+    };
+    if format == Format::Plain {
+        return run_action(&act, conn);
+    }
+    if act.wants_episodes() {
+        let records: Vec<_> = act
+            .search_episodes(&conn)?
+            .iter()
+            .map(|hit| output::SearchRecord {
+                item: output::EpisodeRecord::from(&hit.item),
+                snippet: hit.snippet.clone(),
+            })
+            .collect();
+        output::print_list(format, &records);
+    } else {
+        let records: Vec<_> = act
+            .search_podcasts(&conn)?
+            .iter()
+            .map(|hit| output::SearchRecord {
+                item: output::PodcastRecord::from(&hit.item),
+                snippet: hit.snippet.clone(),
+            })
+            .collect();
+        output::print_list(format, &records);
     }
+    Ok(())
 }
 
-fn do_download(db_name: String, id: i64) {
-    let conn = db::init_db(&db_name).unwrap();
-    let ep = db::fetch_episode(&conn, id).unwrap();
-    let enclosure = ep.enclosure.unwrap();
-    let data = feed::fetch_enclosure(&enclosure).unwrap();
-    let fname = slug::slugify(ep.title) + ".mp3";
-    std::fs::write(fname, data).unwrap();
+fn do_import(db_name: String, file: String, jobs: usize, dry_run: bool) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let act = actions::Import {
+        file,
+        jobs,
+        dry_run,
+    };
+    run_action(&act, conn)
+}
+
+fn do_export(
+    db_name: String,
+    file: String,
+    extended: bool,
+    owner_name: Option<String>,
+) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let act = actions::Export {
+        file,
+        owner_name,
+        extended,
+    };
+    run_action(&act, conn)
+}
+
+fn do_tui(
+    db_name: String,
+    config: &config::Config,
+    jobs: usize,
+    timeout: u64,
+    download: DownloadSettings,
+) -> Result<()> {
+    let update = actions::Update {
+        id: None,
+        tagged: None,
+        jobs,
+        timeout: std::time::Duration::from_secs(timeout),
+        download_dir: download.dir,
+        template: download.template,
+        tag: download.tag,
+    };
+    tui::start(db_name, config, update)
+}
+
+fn do_play(db_name: String, id: i64, player: Option<String>) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    run_action(&actions::Play { id, player }, conn)
+}
+
+fn do_set_played(db_name: String, id: i64, played: bool) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let ep = db::fetch_episode(&conn, id).or_not_found(|| no_episode(id))?;
+    db::set_played(&conn, id, played)?;
+    println!(
+        "Marked {} as {}.",
+        ep.title,
+        if played { "played" } else { "unplayed" }
+    );
+    Ok(())
 }
 
-fn do_search(db_name: String, term: String, detailed: bool, episodes: bool, id: Option<i64>) {
-    let conn = db::init_db(&db_name).unwrap();
-    match (episodes, id) {
-        (true, Some(id)) => {
-            let eps = db::search_episodes(&conn, term, id).unwrap();
-            for ep in eps {
-                ep.print(detailed);
+fn do_queue(db_name: String, command: QueueCommands) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    match command {
+        QueueCommands::Add { id } => {
+            let ep = db::fetch_episode(&conn, id).or_not_found(|| no_episode(id))?;
+            db::queue_add(&conn, id)?;
+            println!("Queued {}.", ep.title);
+        }
+        QueueCommands::List { detailed } => {
+            for (i, ep) in db::fetch_queue(&conn)?.iter().enumerate() {
+                println!("{}. {}", i + 1, ep.str(detailed));
             }
         }
-        (true, None) => {
-            let eps = db::search_episodes(&conn, term, 0).unwrap();
-            for ep in eps {
-                ep.print(detailed);
+        QueueCommands::Remove { id } => {
+            if !db::queue_remove(&conn, id)? {
+                return Err(not_queued(id));
             }
+            println!("Removed {} from the queue.", id);
+        }
+        QueueCommands::Move { id, position } => {
+            if !db::queue_move(&conn, id, position)? {
+                return Err(not_queued(id));
+            }
+            println!("Moved {} to position {}.", id, position);
+        }
+        QueueCommands::Clear => {
+            db::queue_clear(&conn)?;
+            println!("Cleared the queue.");
         }
-        _ => {
-            let pods = db::search_podcasts(&conn, term).unwrap();
-            for pod in pods {
-                pod.print(detailed);
+    }
+    Ok(())
+}
+
+fn do_set_starred(db_name: String, id: i64, starred: bool) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let ep = db::fetch_episode(&conn, id).or_not_found(|| no_episode(id))?;
+    db::set_starred(&conn, id, starred)?;
+    println!(
+        "{} {}.",
+        if starred { "Starred" } else { "Unstarred" },
+        ep.title
+    );
+    Ok(())
+}
+
+fn do_backup(db_name: String, file: std::path::PathBuf) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    run_action(&actions::Backup { file }, conn)
+}
+
+fn do_restore(db_name: String, file: std::path::PathBuf) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    run_action(&actions::Restore { file }, conn)
+}
+
+fn do_tag(db_name: String, id: i64, tags: Vec<String>, add: bool) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let pod = db::fetch_podcast(&conn, id).or_not_found(|| no_podcast(id))?;
+    for tag in &tags {
+        let tag = podcast::clean_tag(tag)
+            .ok_or_else(|| Error::Parse(format!("{:?} isn't much of a tag.", tag)))?;
+        if add {
+            if db::tag_podcast(&conn, id, &tag)? {
+                println!("Tagged {} {}.", pod.title, tag);
+            } else {
+                println!("{} was already tagged {}.", pod.title, tag);
             }
+        } else if db::untag_podcast(&conn, id, &tag)? {
+            println!("Untagged {} {}.", pod.title, tag);
+        } else {
+            return Err(Error::NotFound(format!("{} isn't tagged {}.", pod.title, tag)).into());
         }
     }
+    Ok(())
+}
+
+fn do_tags(db_name: String) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    for (tag, count) in db::fetch_tags(&conn)? {
+        println!("{} ({})", tag, count);
+    }
+    Ok(())
 }
 
-fn do_import(db_name: String, file: String) {
-    let contents = std::fs::read_to_string(file).unwrap();
-    let opml = OPML::from_str(&contents).unwrap();
-    opml.body.outlines.iter().for_each(|o| {
-        if let Some(ref url) = o.xml_url {
-            do_add(db_name.clone(), url.clone())
+fn do_policy(db_name: String, command: PolicyCommands) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    match command {
+        PolicyCommands::Show { id } => {
+            let pod = db::fetch_podcast(&conn, id).or_not_found(|| no_podcast(id))?;
+            println!("{}", pod.title);
+            print!("{}", db::fetch_policy(&conn, id)?);
+        }
+        PolicyCommands::Set {
+            id,
+            auto_download,
+            keep_last,
+            max_age_days,
+            delete_played,
+            speed,
+        } => {
+            let pod = db::fetch_podcast(&conn, id).or_not_found(|| no_podcast(id))?;
+            let mut policy = db::fetch_policy(&conn, id)?;
+            if let Some(auto_download) = auto_download {
+                policy.auto_download = auto_download;
+            }
+            if let Some(n) = keep_last {
+                policy.keep_last = Some(n).filter(|n| *n > 0);
+            }
+            if let Some(days) = max_age_days {
+                policy.max_age_days = Some(days).filter(|d| *d > 0);
+            }
+            if let Some(delete_played) = delete_played {
+                policy.delete_played = delete_played;
+            }
+            if let Some(speed) = speed {
+                policy.speed = Some(speed).filter(|s| *s > 0.0);
+            }
+            db::save_policy(&conn, id, &policy)?;
+            println!("{}", pod.title);
+            print!("{}", policy);
         }
-    });
-}
-
-fn do_export(db_name: String, file: String) {
-    let conn = db::init_db(&db_name).unwrap();
-    let pods = db::fetch_all_podcasts(&conn).unwrap();
-    let opml = OPML {
-        version: "2.0".to_string(),
-        head: None,
-        body: opml::Body {
-            outlines: pods
-                .iter()
-                .map(|p| opml::Outline {
-                    text: p.title.clone(),
-                    r#type: Some("rss".to_string()),
-                    title: Some(p.title.clone()),
-                    html_url: p.link.clone(),
-                    xml_url: Some(p.rss_url.clone()),
-                    ..Default::default()
-                })
-                .collect(),
+        PolicyCommands::Quota { size: None } => match db::fetch_setting(&conn, db::DISK_QUOTA)? {
+            Some(bytes) => println!("Downloads may take up {} bytes.", bytes),
+            None => println!("There is no disk quota."),
         },
+        PolicyCommands::Quota { size: Some(size) } => {
+            if size.eq_ignore_ascii_case("off") {
+                db::save_setting(&conn, db::DISK_QUOTA, None)?;
+                println!("Removed the disk quota.");
+                return Ok(());
+            }
+            let bytes = parse_size(&size).ok_or_else(|| {
+                Error::Parse(format!(
+                    "Can't make sense of the size {:?}; try 500M or 10G.",
+                    size
+                ))
+            })?;
+            db::save_setting(&conn, db::DISK_QUOTA, Some(&bytes.to_string()))?;
+            println!("Downloads may take up {} bytes.", bytes);
+        }
+    }
+    Ok(())
+}
+
+// Reads sizes like 1500000, 500K, 500M or 10G, in powers of 1000.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let scale = match unit.trim().to_uppercase().trim_end_matches('B') {
+        "" => 1e0,
+        "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        _ => return None,
     };
-    std::fs::write(file, opml.to_string().unwrap()).unwrap();
+    let number: f64 = number.parse().ok()?;
+    Some((number * scale) as u64)
 }
 
-fn do_play(db_name: String, id: i64) {
-    let conn = db::init_db(&db_name).unwrap();
-    let ep = db::fetch_episode(&conn, id).unwrap();
-    let enclosure = ep.enclosure.unwrap();
-    call_mpv(enclosure);
+fn do_cleanup(db_name: String, dry_run: bool) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    run_action(&actions::Cleanup { dry_run }, conn)
 }
 
-fn call_mpv(enclosure: crate::podcast::Enclosure) {
-    std::process::Command::new("mpv")
-        .arg(enclosure.url)
-        .status()
-        .unwrap();
+fn not_queued(id: i64) -> anyhow::Error {
+    Error::NotFound(format!("Episode {} is not queued.", id)).into()
 }
@@ -0,0 +1,127 @@
+use crate::error::Error;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Settings from `$XDG_CONFIG_HOME/dipper/config.toml`, or wherever DIPPER_CONFIG points.
+// Every setting is looked for in the same order, and the first one found wins:
+//
+//   1. the command line flag, e.g. `--dir`
+//   2. the environment variable, e.g. DIPPER_DOWNLOAD_DIR
+//   3. the config file, e.g. `download_dir = "~/Podcasts"`
+//   4. the built-in default
+//
+// A missing config file is the same as an empty one.
+//
+//   db = "~/podcasts.db"                 # DIPPER_DB, default $XDG_DATA_HOME/dipper/dipper.db
+//   download_dir = "~/Podcasts"          # DIPPER_DOWNLOAD_DIR, default $XDG_DATA_HOME/dipper/downloads
+//   filename_template = "{podcast}/{title}.{ext}"   # DIPPER_FILENAME_TEMPLATE
+//   tag = true                           # DIPPER_TAG
+//   player = "mpv --no-video"            # DIPPER_PLAYER, default the built-in player
+//                                        # "{start}" is the second to resume from; the
+//                                        # built-in player alone saves progress
+//   jobs = 8                             # DIPPER_JOBS, default 4
+//   timeout = 60                         # DIPPER_TIMEOUT, seconds to wait on a feed, default 30
+//   update_interval = 60                 # minutes between updates in the TUI, default never
+//   owner_name = "Jane Doe"              # DIPPER_OWNER_NAME, for exported OPML files
+//
+//   [keys]                               # TUI actions and the keys that do them
+//   quit = "q"
+//   play = "enter"
+//
+//   [colours]                            # names like "yellow" or "light blue", or "#rrggbb"
+//   accent = "yellow"
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db: Option<PathBuf>,
+    pub download_dir: Option<PathBuf>,
+    pub filename_template: Option<String>,
+    pub tag: Option<bool>,
+    pub player: Option<String>,
+    pub jobs: Option<usize>,
+    pub timeout: Option<u64>,
+    pub update_interval: Option<u64>,
+    pub owner_name: Option<String>,
+    pub keys: HashMap<String, String>,
+    pub colours: Colours,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colours {
+    // Titles, highlighted list entries and the progress bar.
+    pub accent: Option<String>,
+    // The tab names.
+    pub tabs: Option<String>,
+    // Behind the selected tab and the rest of the progress bar.
+    pub background: Option<String>,
+    // The line under the tabs.
+    pub border: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Config> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(anyhow!("Can't read {}: {}", path.display(), e)),
+        };
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|e| Error::Parse(format!("Can't load {}: {}", path.display(), e)))?;
+        config.db = config.db.map(expand_home);
+        config.download_dir = config.download_dir.map(expand_home);
+        Ok(config)
+    }
+
+    pub fn db(&self) -> PathBuf {
+        match &self.db {
+            Some(db) => db.clone(),
+            None => data_dir()
+                .map(|dir| dir.join("dipper.db"))
+                .unwrap_or_else(|| PathBuf::from("dipper.db")),
+        }
+    }
+
+    pub fn download_dir(&self) -> PathBuf {
+        match &self.download_dir {
+            Some(dir) => dir.clone(),
+            None => data_dir()
+                .map(|dir| dir.join("downloads"))
+                .unwrap_or_else(|| PathBuf::from(".")),
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("DIPPER_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("dipper").join("config.toml"))
+}
+
+fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("dipper"))
+}
+
+// An XDG base directory, falling back to its usual place under $HOME. The spec says to
+// ignore relative paths.
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+}
+
+// Lets paths in the config file start with `~/`, as they usually do in hand-written ones.
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path,
+    }
+}
@@ -1,46 +1,328 @@
 use crate::podcast;
+use rusqlite::OptionalExtension;
+
+// Each entry upgrades the schema by one version. The index into this slice plus one is the
+// `user_version` the database is at once the migration has been applied. Never edit a
+// migration that has shipped; append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema. Uses IF NOT EXISTS so databases created before versioning existed
+    // are adopted as-is.
+    "CREATE TABLE IF NOT EXISTS podcasts (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL,
+        rss_url TEXT NOT NULL UNIQUE,
+        link TEXT,
+        language TEXT,
+        pub_date TEXT,
+        last_build_date TEXT
+    );
+    CREATE TABLE IF NOT EXISTS episodes (
+        id INTEGER PRIMARY KEY,
+        podcast_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        guid TEXT NOT NULL UNIQUE,
+        description TEXT NOT NULL,
+        pub_date TEXT,
+        link TEXT,
+        enclosure_url TEXT NOT NULL UNIQUE,
+        enclosure_length TEXT,
+        enclosure_mime_type TEXT,
+        FOREIGN KEY (podcast_id) REFERENCES podcasts(id)
+    );",
+    // 2: per-episode playback state.
+    "ALTER TABLE episodes ADD COLUMN played INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE episodes ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE episodes ADD COLUMN duration INTEGER;
+    ALTER TABLE episodes ADD COLUMN last_played TEXT;",
+    // 3: the Up Next queue. Positions are kept dense, starting at 1.
+    "CREATE TABLE queue (
+        episode_id INTEGER NOT NULL UNIQUE,
+        position INTEGER NOT NULL,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );",
+    // 4: iTunes podcast namespace. Categories are stored newline-separated.
+    "ALTER TABLE podcasts ADD COLUMN author TEXT;
+    ALTER TABLE podcasts ADD COLUMN image TEXT;
+    ALTER TABLE podcasts ADD COLUMN summary TEXT;
+    ALTER TABLE podcasts ADD COLUMN explicit INTEGER;
+    ALTER TABLE podcasts ADD COLUMN categories TEXT;
+    ALTER TABLE podcasts ADD COLUMN block INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE episodes ADD COLUMN author TEXT;
+    ALTER TABLE episodes ADD COLUMN image TEXT;
+    ALTER TABLE episodes ADD COLUMN summary TEXT;
+    ALTER TABLE episodes ADD COLUMN explicit INTEGER;
+    ALTER TABLE episodes ADD COLUMN episode_number INTEGER;
+    ALTER TABLE episodes ADD COLUMN season INTEGER;
+    ALTER TABLE episodes ADD COLUMN episode_type TEXT;
+    ALTER TABLE episodes ADD COLUMN block INTEGER NOT NULL DEFAULT 0;",
+    // 5: Podcasting 2.0 namespace.
+    "ALTER TABLE podcasts ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE episodes ADD COLUMN season_name TEXT;
+    ALTER TABLE episodes ADD COLUMN episode_display TEXT;
+    CREATE TABLE funding (
+        id INTEGER PRIMARY KEY,
+        podcast_id INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        text TEXT NOT NULL,
+        FOREIGN KEY (podcast_id) REFERENCES podcasts(id)
+    );
+    CREATE TABLE chapters (
+        episode_id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL,
+        mime_type TEXT,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );
+    CREATE TABLE transcripts (
+        id INTEGER PRIMARY KEY,
+        episode_id INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        mime_type TEXT NOT NULL,
+        language TEXT,
+        rel TEXT,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );
+    CREATE TABLE persons (
+        id INTEGER PRIMARY KEY,
+        episode_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        role TEXT,
+        person_group TEXT,
+        img TEXT,
+        href TEXT,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );
+    CREATE TABLE soundbites (
+        id INTEGER PRIMARY KEY,
+        episode_id INTEGER NOT NULL,
+        start_time REAL NOT NULL,
+        duration REAL NOT NULL,
+        title TEXT,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );",
+    // 6: HTTP validators and a hash of the last feed body, for conditional updates.
+    "ALTER TABLE podcasts ADD COLUMN etag TEXT;
+    ALTER TABLE podcasts ADD COLUMN last_modified TEXT;
+    ALTER TABLE podcasts ADD COLUMN content_hash TEXT;",
+    // 7: edited and removed episodes. A revision holds the enclosure an episode had before
+    // the publisher replaced it.
+    "ALTER TABLE episodes ADD COLUMN removed INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE episode_revisions (
+        id INTEGER PRIMARY KEY,
+        episode_id INTEGER NOT NULL,
+        changed_at TEXT NOT NULL,
+        enclosure_url TEXT,
+        enclosure_length TEXT,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );",
+    // 8: guids and enclosures only have to be unique within a podcast, and an episode may
+    // come without an enclosure. SQLite can't alter constraints, so the table is rebuilt.
+    "CREATE TABLE episodes_new (
+        id INTEGER PRIMARY KEY,
+        podcast_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        guid TEXT NOT NULL,
+        description TEXT NOT NULL,
+        pub_date TEXT,
+        link TEXT,
+        enclosure_url TEXT,
+        enclosure_length TEXT,
+        enclosure_mime_type TEXT,
+        played INTEGER NOT NULL DEFAULT 0,
+        position INTEGER NOT NULL DEFAULT 0,
+        duration INTEGER,
+        last_played TEXT,
+        author TEXT,
+        image TEXT,
+        summary TEXT,
+        explicit INTEGER,
+        episode_number INTEGER,
+        season INTEGER,
+        episode_type TEXT,
+        block INTEGER NOT NULL DEFAULT 0,
+        season_name TEXT,
+        episode_display TEXT,
+        removed INTEGER NOT NULL DEFAULT 0,
+        UNIQUE (podcast_id, guid),
+        UNIQUE (podcast_id, enclosure_url),
+        FOREIGN KEY (podcast_id) REFERENCES podcasts(id)
+    );
+    INSERT INTO episodes_new (id, podcast_id, title, guid, description, pub_date, link, enclosure_url,
+        enclosure_length, enclosure_mime_type, played, position, duration, last_played, author,
+        image, summary, explicit, episode_number, season, episode_type, block, season_name,
+        episode_display, removed)
+    SELECT id, podcast_id, title, guid, description, pub_date, link, enclosure_url,
+        enclosure_length, enclosure_mime_type, played, position, duration, last_played, author,
+        image, summary, explicit, episode_number, season, episode_type, block, season_name,
+        episode_display, removed FROM episodes;
+    DROP TABLE episodes;
+    ALTER TABLE episodes_new RENAME TO episodes;",
+    // 9: full-text search over titles, descriptions and show notes. The indexes read their
+    // text from the tables themselves and are kept in step by triggers. Progress updates
+    // during playback don't touch the indexed columns, so they don't reindex anything.
+    "CREATE VIRTUAL TABLE podcasts_fts USING fts5(
+        title, description, summary, content='podcasts', content_rowid='id'
+    );
+    CREATE TRIGGER podcasts_fts_insert AFTER INSERT ON podcasts BEGIN
+        INSERT INTO podcasts_fts (rowid, title, description, summary)
+        VALUES (new.id, new.title, new.description, new.summary);
+    END;
+    CREATE TRIGGER podcasts_fts_delete AFTER DELETE ON podcasts BEGIN
+        INSERT INTO podcasts_fts (podcasts_fts, rowid, title, description, summary)
+        VALUES ('delete', old.id, old.title, old.description, old.summary);
+    END;
+    CREATE TRIGGER podcasts_fts_update AFTER UPDATE OF title, description, summary ON podcasts BEGIN
+        INSERT INTO podcasts_fts (podcasts_fts, rowid, title, description, summary)
+        VALUES ('delete', old.id, old.title, old.description, old.summary);
+        INSERT INTO podcasts_fts (rowid, title, description, summary)
+        VALUES (new.id, new.title, new.description, new.summary);
+    END;
+    INSERT INTO podcasts_fts (podcasts_fts) VALUES ('rebuild');
+    CREATE VIRTUAL TABLE episodes_fts USING fts5(
+        title, description, summary, content='episodes', content_rowid='id'
+    );
+    CREATE TRIGGER episodes_fts_insert AFTER INSERT ON episodes BEGIN
+        INSERT INTO episodes_fts (rowid, title, description, summary)
+        VALUES (new.id, new.title, new.description, new.summary);
+    END;
+    CREATE TRIGGER episodes_fts_delete AFTER DELETE ON episodes BEGIN
+        INSERT INTO episodes_fts (episodes_fts, rowid, title, description, summary)
+        VALUES ('delete', old.id, old.title, old.description, old.summary);
+    END;
+    CREATE TRIGGER episodes_fts_update AFTER UPDATE OF title, description, summary ON episodes BEGIN
+        INSERT INTO episodes_fts (episodes_fts, rowid, title, description, summary)
+        VALUES ('delete', old.id, old.title, old.description, old.summary);
+        INSERT INTO episodes_fts (rowid, title, description, summary)
+        VALUES (new.id, new.title, new.description, new.summary);
+    END;
+    INSERT INTO episodes_fts (episodes_fts) VALUES ('rebuild');",
+    // 10: episodes downloaded for offline listening.
+    "CREATE TABLE downloads (
+        episode_id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        downloaded_at TEXT NOT NULL,
+        FOREIGN KEY (episode_id) REFERENCES episodes(id)
+    );",
+    // 11: starred episodes, per-podcast download and retention policies, and app-wide
+    // settings such as the disk quota.
+    "ALTER TABLE episodes ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE policies (
+        podcast_id INTEGER PRIMARY KEY,
+        auto_download INTEGER NOT NULL DEFAULT 0,
+        keep_last INTEGER,
+        max_age_days INTEGER,
+        delete_played INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (podcast_id) REFERENCES podcasts(id)
+    );
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 12: tags, which a podcast can have any number of.
+    "CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE
+    );
+    CREATE TABLE podcast_tags (
+        podcast_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY (podcast_id, tag_id),
+        FOREIGN KEY (podcast_id) REFERENCES podcasts(id),
+        FOREIGN KEY (tag_id) REFERENCES tags(id)
+    );",
+    // 13: the speed each podcast plays at.
+    "ALTER TABLE policies ADD COLUMN speed REAL;",
+];
+
+// Qualified like EPISODE_COLUMNS below. The tags come back one per line.
+const PODCAST_COLUMNS: &str = "podcasts.id, podcasts.title, podcasts.description,
+    podcasts.rss_url, podcasts.link, podcasts.language, podcasts.pub_date,
+    podcasts.last_build_date, podcasts.author, podcasts.image, podcasts.summary,
+    podcasts.explicit, podcasts.categories, podcasts.block, podcasts.locked,
+    (SELECT group_concat(tags.name, char(10)) FROM podcast_tags
+        JOIN tags ON tags.id = podcast_tags.tag_id
+        WHERE podcast_tags.podcast_id = podcasts.id)";
+
+// Qualified so it can be used in joins with tables that share column names.
+const EPISODE_COLUMNS: &str = "episodes.id, episodes.title, episodes.guid, episodes.description,
+    episodes.pub_date, episodes.link, episodes.enclosure_url, episodes.enclosure_length,
+    episodes.enclosure_mime_type, episodes.played, episodes.position, episodes.duration,
+    episodes.last_played, episodes.author, episodes.image, episodes.summary, episodes.explicit,
+    episodes.episode_number, episodes.season, episodes.episode_type, episodes.block,
+    episodes.season_name, episodes.episode_display, episodes.removed, episodes.starred,
+    (SELECT path FROM downloads WHERE downloads.episode_id = episodes.id)";
 
 pub fn init_db(dbfname: &String) -> Result<rusqlite::Connection, rusqlite::Error> {
-    let conn = rusqlite::Connection::open(dbfname)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS podcasts (
-            id INTEGER PRIMARY KEY,
-            title TEXT NOT NULL,
-            description TEXT NOT NULL,
-            rss_url TEXT NOT NULL UNIQUE,
-            link TEXT,
-            language TEXT,
-            pub_date TEXT,
-            last_build_date TEXT
-        )",
-        (),
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS episodes (
-            id INTEGER PRIMARY KEY,
-            podcast_id INTEGER NOT NULL,
-            title TEXT NOT NULL,
-            guid TEXT NOT NULL UNIQUE,
-            description TEXT NOT NULL,
-            pub_date TEXT,
-            link TEXT,
-            enclosure_url TEXT NOT NULL UNIQUE,
-            enclosure_length TEXT,
-            enclosure_mime_type TEXT,
-            FOREIGN KEY (podcast_id) REFERENCES podcasts(id)
-        )",
+    let mut conn = rusqlite::Connection::open(dbfname)?;
+    // The TUI's player and background updates each have a connection of their own.
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    migrate(&mut conn, dbfname)?;
+    Ok(conn)
+}
+
+pub fn schema_version(conn: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+fn migrate(conn: &mut rusqlite::Connection, dbfname: &String) -> Result<(), rusqlite::Error> {
+    let current = schema_version(conn)?;
+    let latest = MIGRATIONS.len();
+    if current > latest {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+            Some(format!(
+                "{} is at schema version {}, but this dipper only knows up to version {}",
+                dbfname, current, latest
+            )),
+        ));
+    }
+    if current == latest {
+        return Ok(());
+    }
+    if has_tables(conn)? {
+        backup_db(conn, dbfname, current)?;
+    }
+    let tx = conn.transaction()?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+    }
+    tx.commit()
+}
+
+fn has_tables(conn: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
         (),
+        |row| row.get(0),
     )?;
-    Ok(conn)
+    Ok(count > 0)
+}
+
+// Snapshots the database next to itself before it is migrated, e.g. `dipper.db.v1.bak`.
+// An existing backup for the same version is left alone so the oldest copy survives.
+fn backup_db(
+    conn: &rusqlite::Connection,
+    dbfname: &String,
+    version: usize,
+) -> Result<(), rusqlite::Error> {
+    let backup = format!("{}.v{}.bak", dbfname, version);
+    if std::path::Path::new(&backup).exists() {
+        return Ok(());
+    }
+    conn.execute("VACUUM INTO ?1", rusqlite::params![backup])?;
+    Ok(())
 }
 
 pub fn insert_podcast(
     conn: &rusqlite::Connection,
     podcast: &mut podcast::Podcast,
 ) -> Result<(), rusqlite::Error> {
-    conn.execute(
-        "INSERT OR IGNORE INTO podcasts (title, description, rss_url, link, language, pub_date, last_build_date)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO podcasts (title, description, rss_url, link, language, pub_date, last_build_date,
+            author, image, summary, explicit, categories, block, locked)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
         rusqlite::params![
             podcast.title,
             podcast.description,
@@ -49,36 +331,214 @@ pub fn insert_podcast(
             podcast.language,
             podcast.pub_date,
             podcast.last_build_date,
+            podcast.author,
+            podcast.image,
+            podcast.summary,
+            podcast.explicit,
+            podcast.categories.join("\n"),
+            podcast.block,
+            podcast.locked,
         ],
     )?;
     podcast.id = conn.last_insert_rowid();
+    if inserted > 0 {
+        for funding in &podcast.funding {
+            conn.execute(
+                "INSERT INTO funding (podcast_id, url, text) VALUES (?1, ?2, ?3)",
+                rusqlite::params![podcast.id, funding.url, funding.text],
+            )?;
+        }
+    }
     for episode in &podcast.episodes {
-        insert_episode(conn, episode, podcast.id)?;
+        upsert_episode(conn, episode, podcast.id)?;
+    }
+    Ok(())
+}
+
+// Rewrites a podcast's details from a fresh copy of its feed, funding links included. The
+// feed URL, tags and policy are ours rather than the feed's, so they stay as they were.
+// Categories are merged rather than replaced: the feed's come first, followed by any it
+// doesn't have, such as the folders of an imported OPML file. A category the feed drops
+// is kept, as there's no telling it apart from one of those.
+pub fn update_podcast(
+    conn: &rusqlite::Connection,
+    id: i64,
+    podcast: &podcast::Podcast,
+) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    let stored: Option<String> = tx.query_row(
+        "SELECT categories FROM podcasts WHERE id = ?1",
+        rusqlite::params![id],
+        |row| row.get(0),
+    )?;
+    let mut categories = podcast.categories.clone();
+    for category in stored.iter().flat_map(|c| c.lines()) {
+        if !categories.iter().any(|c| c == category) {
+            categories.push(category.to_string());
+        }
+    }
+    tx.execute(
+        "UPDATE podcasts SET title = ?2, description = ?3, link = ?4, language = ?5,
+            pub_date = ?6, last_build_date = ?7, author = ?8, image = ?9, summary = ?10,
+            explicit = ?11, categories = ?12, block = ?13, locked = ?14
+        WHERE id = ?1",
+        rusqlite::params![
+            id,
+            podcast.title,
+            podcast.description,
+            podcast.link,
+            podcast.language,
+            podcast.pub_date,
+            podcast.last_build_date,
+            podcast.author,
+            podcast.image,
+            podcast.summary,
+            podcast.explicit,
+            categories.join("\n"),
+            podcast.block,
+            podcast.locked,
+        ],
+    )?;
+    tx.execute(
+        "DELETE FROM funding WHERE podcast_id = ?1",
+        rusqlite::params![id],
+    )?;
+    for funding in &podcast.funding {
+        tx.execute(
+            "INSERT INTO funding (podcast_id, url, text) VALUES (?1, ?2, ?3)",
+            rusqlite::params![id, funding.url, funding.text],
+        )?;
     }
+    tx.commit()
+}
+
+// The id of the podcast with this feed, if there is one.
+pub fn fetch_podcast_id_by_url(
+    conn: &rusqlite::Connection,
+    rss_url: &str,
+) -> Result<Option<i64>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id FROM podcasts WHERE rss_url = ?1",
+        rusqlite::params![rss_url],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn fetch_feed_cache(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+) -> Result<podcast::FeedCache, rusqlite::Error> {
+    conn.query_row(
+        "SELECT etag, last_modified, content_hash FROM podcasts WHERE id = ?1",
+        rusqlite::params![podcast_id],
+        |row| {
+            Ok(podcast::FeedCache {
+                etag: row.get(0)?,
+                last_modified: row.get(1)?,
+                content_hash: row.get(2)?,
+            })
+        },
+    )
+}
+
+pub fn save_feed_cache(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+    cache: &podcast::FeedCache,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE podcasts SET etag = ?1, last_modified = ?2, content_hash = ?3 WHERE id = ?4",
+        rusqlite::params![
+            cache.etag,
+            cache.last_modified,
+            cache.content_hash,
+            podcast_id
+        ],
+    )?;
     Ok(())
 }
 
+fn podcast_from_row(row: &rusqlite::Row) -> Result<podcast::Podcast, rusqlite::Error> {
+    Ok(podcast::Podcast {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        description: row.get(2)?,
+        rss_url: row.get(3)?,
+        link: row.get(4)?,
+        language: row.get(5)?,
+        pub_date: row.get(6)?,
+        last_build_date: row.get(7)?,
+        author: row.get(8)?,
+        image: row.get(9)?,
+        summary: row.get(10)?,
+        explicit: row.get(11)?,
+        categories: row
+            .get::<_, Option<String>>(12)?
+            .map(|c| c.lines().map(|l| l.to_string()).collect())
+            .unwrap_or_default(),
+        block: row.get(13)?,
+        locked: row.get(14)?,
+        tags: {
+            let mut tags: Vec<String> = row
+                .get::<_, Option<String>>(15)?
+                .map(|t| t.lines().map(|l| l.to_string()).collect())
+                .unwrap_or_default();
+            tags.sort_by_key(|t| t.to_lowercase());
+            tags
+        },
+        funding: Vec::new(),
+        episodes: Vec::new(),
+    })
+}
+
+fn episode_from_row(row: &rusqlite::Row) -> Result<podcast::Episode, rusqlite::Error> {
+    Ok(podcast::Episode {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        guid: row.get(2)?,
+        description: row.get(3)?,
+        pub_date: row.get(4)?,
+        link: row.get(5)?,
+        enclosure: match row.get(6)? {
+            Some(url) => Some(podcast::Enclosure {
+                url,
+                length: row.get(7)?,
+                mime_type: row.get(8)?,
+            }),
+            None => None,
+        },
+        played: row.get(9)?,
+        position: row.get(10)?,
+        duration: row.get(11)?,
+        last_played: row.get(12)?,
+        author: row.get(13)?,
+        image: row.get(14)?,
+        summary: row.get(15)?,
+        explicit: row.get(16)?,
+        episode_number: row.get(17)?,
+        season: row.get(18)?,
+        episode_type: row.get(19)?,
+        block: row.get(20)?,
+        season_name: row.get(21)?,
+        episode_display: row.get(22)?,
+        removed: row.get(23)?,
+        starred: row.get(24)?,
+        download_path: row.get(25)?,
+        chapters: None,
+        transcripts: Vec::new(),
+        persons: Vec::new(),
+        soundbites: Vec::new(),
+        revisions: Vec::new(),
+    })
+}
+
 pub fn fetch_all_podcasts(
     conn: &rusqlite::Connection,
 ) -> Result<Vec<podcast::Podcast>, rusqlite::Error> {
     let mut ret = Vec::new();
-    let mut pod_stmt = conn.prepare(
-        "SELECT id, title, description, rss_url, link, language, pub_date, last_build_date
-        FROM podcasts",
-    )?;
-    let pods = pod_stmt.query_map(rusqlite::params![], |row| {
-        Ok(podcast::Podcast {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: row.get(2)?,
-            rss_url: row.get(3)?,
-            link: row.get(4)?,
-            language: row.get(5)?,
-            pub_date: row.get(6)?,
-            last_build_date: row.get(7)?,
-            episodes: Vec::new(),
-        })
-    })?;
+    let mut pod_stmt = conn.prepare(&format!("SELECT {} FROM podcasts", PODCAST_COLUMNS))?;
+    let pods = pod_stmt.query_map(rusqlite::params![], podcast_from_row)?;
     for pod in pods {
         ret.push(pod?);
     }
@@ -88,27 +548,9 @@ pub fn fetch_all_podcasts(
 pub fn fetch_all_podcasts_and_episodes(
     conn: &rusqlite::Connection,
 ) -> Result<Vec<podcast::Podcast>, rusqlite::Error> {
-    let mut ret = Vec::new();
-    let mut pod_stmt = conn.prepare(
-        "SELECT id, title, description, rss_url, link, language, pub_date, last_build_date
-        FROM podcasts",
-    )?;
-    let pods = pod_stmt.query_map(rusqlite::params![], |row| {
-        let episodes = fetch_episodes(conn, row.get(0)?).unwrap();
-        Ok(podcast::Podcast {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: row.get(2)?,
-            rss_url: row.get(3)?,
-            link: row.get(4)?,
-            language: row.get(5)?,
-            pub_date: row.get(6)?,
-            last_build_date: row.get(7)?,
-            episodes,
-        })
-    })?;
-    for pod in pods {
-        ret.push(pod?);
+    let mut ret = fetch_all_podcasts(conn)?;
+    for pod in ret.iter_mut() {
+        pod.episodes = fetch_episodes(conn, pod.id)?;
     }
     Ok(ret)
 }
@@ -118,29 +560,11 @@ pub fn fetch_episodes(
     id: i64,
 ) -> Result<Vec<podcast::Episode>, rusqlite::Error> {
     let mut ret = Vec::new();
-    let mut ep_stmt = conn.prepare(
-        "SELECT id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type
-        FROM episodes
-        WHERE podcast_id = ?1",
-    )?;
-    let eps = ep_stmt.query_map(rusqlite::params![id], |row| {
-        Ok(podcast::Episode {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            guid: row.get(2)?,
-            description: row.get(3)?,
-            pub_date: row.get(4)?,
-            link: row.get(5)?,
-            enclosure: match row.get(6)? {
-                Some(url) => Some(podcast::Enclosure {
-                    url,
-                    length: row.get(7)?,
-                    mime_type: row.get(8)?,
-                }),
-                None => None,
-            },
-        })
-    })?;
+    let mut ep_stmt = conn.prepare(&format!(
+        "SELECT {} FROM episodes WHERE podcast_id = ?1",
+        EPISODE_COLUMNS
+    ))?;
+    let eps = ep_stmt.query_map(rusqlite::params![id], episode_from_row)?;
     for ep in eps {
         ret.push(ep?);
     }
@@ -151,54 +575,155 @@ pub fn fetch_episode(
     conn: &rusqlite::Connection,
     id: i64,
 ) -> Result<podcast::Episode, rusqlite::Error> {
-    let mut ep_stmt = conn.prepare(
-        "SELECT id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type
-        FROM episodes
-        WHERE id = ?1",
-    )?;
-    let ep = ep_stmt.query_row(rusqlite::params![id], |row| {
-        Ok(podcast::Episode {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            guid: row.get(2)?,
-            description: row.get(3)?,
-            pub_date: row.get(4)?,
-            link: row.get(5)?,
-            enclosure: match row.get(6)? {
-                Some(url) => Some(podcast::Enclosure {
-                    url,
-                    length: row.get(7)?,
-                    mime_type: row.get(8)?,
-                }),
-                None => None,
-            },
-        })
-    })?;
+    let mut ep_stmt = conn.prepare(&format!(
+        "SELECT {} FROM episodes WHERE id = ?1",
+        EPISODE_COLUMNS
+    ))?;
+    let mut ep = ep_stmt.query_row(rusqlite::params![id], episode_from_row)?;
+    fetch_episode_extras(conn, &mut ep)?;
     Ok(ep)
 }
 
+// Loads the Podcasting 2.0 data and revision history kept outside the episodes table.
+// Listings skip this, so only episodes fetched one at a time come with it filled in.
+pub fn fetch_episode_extras(
+    conn: &rusqlite::Connection,
+    ep: &mut podcast::Episode,
+) -> Result<(), rusqlite::Error> {
+    ep.chapters = conn
+        .query_row(
+            "SELECT url, mime_type FROM chapters WHERE episode_id = ?1",
+            rusqlite::params![ep.id],
+            |row| {
+                Ok(podcast::Chapters {
+                    url: row.get(0)?,
+                    mime_type: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+    let mut stmt = conn.prepare(
+        "SELECT url, mime_type, language, rel FROM transcripts WHERE episode_id = ?1 ORDER BY id",
+    )?;
+    ep.transcripts = stmt
+        .query_map(rusqlite::params![ep.id], |row| {
+            Ok(podcast::Transcript {
+                url: row.get(0)?,
+                mime_type: row.get(1)?,
+                language: row.get(2)?,
+                rel: row.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(
+        "SELECT name, role, person_group, img, href FROM persons WHERE episode_id = ?1 ORDER BY id",
+    )?;
+    ep.persons = stmt
+        .query_map(rusqlite::params![ep.id], |row| {
+            Ok(podcast::Person {
+                name: row.get(0)?,
+                role: row.get(1)?,
+                group: row.get(2)?,
+                img: row.get(3)?,
+                href: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(
+        "SELECT start_time, duration, title FROM soundbites WHERE episode_id = ?1 ORDER BY start_time",
+    )?;
+    ep.soundbites = stmt
+        .query_map(rusqlite::params![ep.id], |row| {
+            Ok(podcast::Soundbite {
+                start: row.get(0)?,
+                duration: row.get(1)?,
+                title: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(
+        "SELECT changed_at, enclosure_url, enclosure_length FROM episode_revisions
+        WHERE episode_id = ?1 ORDER BY id",
+    )?;
+    ep.revisions = stmt
+        .query_map(rusqlite::params![ep.id], |row| {
+            Ok(podcast::Revision {
+                changed_at: row.get(0)?,
+                enclosure_url: row.get(1)?,
+                enclosure_length: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(())
+}
+
+fn delete_episode_extras(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+) -> Result<(), rusqlite::Error> {
+    for table in ["chapters", "transcripts", "persons", "soundbites"] {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE episode_id = ?1", table),
+            rusqlite::params![episode_id],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_episode_extras(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+    episode: &podcast::Episode,
+) -> Result<(), rusqlite::Error> {
+    if let Some(chapters) = &episode.chapters {
+        conn.execute(
+            "INSERT INTO chapters (episode_id, url, mime_type) VALUES (?1, ?2, ?3)",
+            rusqlite::params![episode_id, chapters.url, chapters.mime_type],
+        )?;
+    }
+    for t in &episode.transcripts {
+        conn.execute(
+            "INSERT INTO transcripts (episode_id, url, mime_type, language, rel)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![episode_id, t.url, t.mime_type, t.language, t.rel],
+        )?;
+    }
+    for p in &episode.persons {
+        conn.execute(
+            "INSERT INTO persons (episode_id, name, role, person_group, img, href)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![episode_id, p.name, p.role, p.group, p.img, p.href],
+        )?;
+    }
+    for sb in &episode.soundbites {
+        conn.execute(
+            "INSERT INTO soundbites (episode_id, start_time, duration, title)
+            VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![episode_id, sb.start, sb.duration, sb.title],
+        )?;
+    }
+    Ok(())
+}
+
 pub fn fetch_podcast(
     conn: &rusqlite::Connection,
     id: i64,
 ) -> Result<podcast::Podcast, rusqlite::Error> {
-    let mut pod_stmt = conn.prepare(
-        "SELECT id, title, description, rss_url, link, language, pub_date, last_build_date
-        FROM podcasts
-        WHERE id = ?1",
-    )?;
-    let pod = pod_stmt.query_row(rusqlite::params![id], |row| {
-        Ok(podcast::Podcast {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: row.get(2)?,
-            rss_url: row.get(3)?,
-            link: row.get(4)?,
-            language: row.get(5)?,
-            pub_date: row.get(6)?,
-            last_build_date: row.get(7)?,
-            episodes: Vec::new(),
-        })
-    })?;
+    let mut pod_stmt = conn.prepare(&format!(
+        "SELECT {} FROM podcasts WHERE podcasts.id = ?1",
+        PODCAST_COLUMNS
+    ))?;
+    let mut pod = pod_stmt.query_row(rusqlite::params![id], podcast_from_row)?;
+    let mut stmt =
+        conn.prepare("SELECT url, text FROM funding WHERE podcast_id = ?1 ORDER BY id")?;
+    pod.funding = stmt
+        .query_map(rusqlite::params![id], |row| {
+            Ok(podcast::Funding {
+                url: row.get(0)?,
+                text: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
     Ok(pod)
 }
 
@@ -207,43 +732,178 @@ pub fn fetch_podcast_and_episodes(
     id: i64,
 ) -> Result<podcast::Podcast, rusqlite::Error> {
     let mut pod = fetch_podcast(conn, id)?;
-    let mut ep_stmt = conn.prepare(
-        "SELECT id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type
-        FROM episodes
-        WHERE podcast_id = ?1")?;
-    ep_stmt
-        .query_map(rusqlite::params![id], |row| {
-            Ok(podcast::Episode {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                guid: row.get(2)?,
-                description: row.get(3)?,
-                pub_date: row.get(4)?,
-                link: row.get(5)?,
-                enclosure: match row.get(6)? {
-                    Some(url) => Some(podcast::Enclosure {
-                        url,
-                        length: row.get(7)?,
-                        mime_type: row.get(8)?,
-                    }),
-                    None => None,
-                },
+    pod.episodes = fetch_episodes(conn, id)?;
+    Ok(pod)
+}
+
+// How an episode from a feed compared with the copy we already had.
+#[derive(Debug, PartialEq)]
+pub enum Upsert {
+    // A new episode, with the id it was stored under.
+    Inserted(i64),
+    Updated,
+    Unchanged,
+}
+
+// Stores an episode from a feed. An episode we already have, matched by guid, is rewritten
+// when the publisher has edited it, keeping its playback state; a replaced enclosure is
+// recorded as a revision first.
+pub fn upsert_episode(
+    conn: &rusqlite::Connection,
+    episode: &podcast::Episode,
+    podcast_id: i64,
+) -> Result<Upsert, rusqlite::Error> {
+    let existing = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM episodes WHERE podcast_id = ?1 AND guid = ?2",
+                EPISODE_COLUMNS
+            ),
+            rusqlite::params![podcast_id, episode.guid],
+            episode_from_row,
+        )
+        .optional()?;
+    let mut old = match existing {
+        Some(old) => old,
+        None => {
+            return Ok(match insert_episode(conn, episode, podcast_id)? {
+                Some(id) => Upsert::Inserted(id),
+                None => Upsert::Unchanged,
             })
+        }
+    };
+    fetch_episode_extras(conn, &mut old)?;
+    if !old.removed && !episode_changed(&old, episode) {
+        return Ok(Upsert::Unchanged);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let url = |e: &podcast::Episode| e.enclosure.as_ref().map(|e| e.url.clone());
+    let length = |e: &podcast::Episode| e.enclosure.as_ref().and_then(|e| e.length.clone());
+    if url(&old) != url(episode) || length(&old) != length(episode) {
+        tx.execute(
+            "INSERT INTO episode_revisions (episode_id, changed_at, enclosure_url, enclosure_length)
+            VALUES (?1, datetime('now'), ?2, ?3)",
+            rusqlite::params![old.id, url(&old), length(&old)],
+        )?;
+    }
+    tx.execute(
+        "UPDATE episodes SET title = ?1, description = ?2, pub_date = ?3, link = ?4,
+            enclosure_url = ?5, enclosure_length = ?6, enclosure_mime_type = ?7,
+            duration = COALESCE(?8, duration), author = ?9, image = ?10, summary = ?11,
+            explicit = ?12, episode_number = ?13, season = ?14, episode_type = ?15, block = ?16,
+            season_name = ?17, episode_display = ?18, removed = 0
+        WHERE id = ?19",
+        rusqlite::params![
+            episode.title,
+            episode.description,
+            episode.pub_date,
+            episode.link,
+            episode.enclosure.as_ref().map(|e| &e.url),
+            episode.enclosure.as_ref().map(|e| &e.length),
+            episode.enclosure.as_ref().map(|e| &e.mime_type),
+            episode.duration,
+            episode.author,
+            episode.image,
+            episode.summary,
+            episode.explicit,
+            episode.episode_number,
+            episode.season,
+            episode.episode_type,
+            episode.block,
+            episode.season_name,
+            episode.episode_display,
+            old.id,
+        ],
+    )?;
+    delete_episode_extras(&tx, old.id)?;
+    insert_episode_extras(&tx, old.id, episode)?;
+    tx.commit()?;
+    Ok(Upsert::Updated)
+}
+
+// Whether anything the feed controls differs between the stored episode and a fresh copy.
+// A feed that doesn't give a duration leaves the one the player found alone.
+fn episode_changed(old: &podcast::Episode, new: &podcast::Episode) -> bool {
+    fn same_items<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+        a.len() == b.len() && a.iter().all(|x| b.contains(x))
+    }
+    old.title != new.title
+        || old.description != new.description
+        || !same_pub_date(old.pub_date.as_deref(), new.pub_date.as_deref())
+        || old.link != new.link
+        || old.enclosure != new.enclosure
+        || (new.duration.is_some() && old.duration != new.duration)
+        || old.author != new.author
+        || old.image != new.image
+        || old.summary != new.summary
+        || old.explicit != new.explicit
+        || old.episode_number != new.episode_number
+        || old.season != new.season
+        || old.episode_type != new.episode_type
+        || old.block != new.block
+        || old.season_name != new.season_name
+        || old.episode_display != new.episode_display
+        || old.chapters != new.chapters
+        || !same_items(&old.transcripts, &new.transcripts)
+        || !same_items(&old.persons, &new.persons)
+        || !same_items(&old.soundbites, &new.soundbites)
+}
+
+// Older versions stored only the day, in the feed's own timezone, where dates are now full
+// UTC timestamps. A day-only date matches any timestamp that falls on that day somewhere, so
+// upgrading doesn't flag every episode as edited.
+fn same_pub_date(old: Option<&str>, new: Option<&str>) -> bool {
+    let (Some(old), Some(new)) = (old, new) else {
+        return old == new;
+    };
+    if old == new {
+        return true;
+    }
+    let day = |d: &str| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok();
+    match (old.len() == 10, day(old), new.get(..10).and_then(day)) {
+        (true, Some(old), Some(new)) => (new - old).num_days().abs() <= 1,
+        _ => false,
+    }
+}
+
+// Flags a podcast's episodes that are missing from the given guids, i.e. from its feed.
+// Returns how many were newly flagged.
+pub fn mark_removed(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+    guids: &[&str],
+) -> Result<usize, rusqlite::Error> {
+    let mut stmt =
+        conn.prepare("SELECT id, guid FROM episodes WHERE podcast_id = ?1 AND removed = 0")?;
+    let gone: Vec<i64> = stmt
+        .query_map(rusqlite::params![podcast_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
         })?
-        .for_each(|ep| {
-            pod.episodes.push(ep.unwrap());
-        });
-    Ok(pod)
+        .filter_map(|row| match row {
+            Ok((id, guid)) if !guids.contains(&guid.as_str()) => Some(Ok(id)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<Result<_, _>>()?;
+    for id in &gone {
+        conn.execute(
+            "UPDATE episodes SET removed = 1 WHERE id = ?1",
+            rusqlite::params![id],
+        )?;
+    }
+    Ok(gone.len())
 }
 
-pub fn insert_episode(
+fn insert_episode(
     conn: &rusqlite::Connection,
     episode: &podcast::Episode,
     podcast_id: i64,
-) -> Result<(), rusqlite::Error> {
-    conn.execute(
-        "INSERT OR IGNORE INTO episodes (podcast_id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+) -> Result<Option<i64>, rusqlite::Error> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO episodes (podcast_id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type,
+            duration, author, image, summary, explicit, episode_number, season, episode_type, block,
+            season_name, episode_display)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
         rusqlite::params![
             podcast_id,
             episode.title,
@@ -254,17 +914,373 @@ pub fn insert_episode(
             episode.enclosure.as_ref().map(|e| &e.url),
             episode.enclosure.as_ref().map(|e| &e.length),
             episode.enclosure.as_ref().map(|e| &e.mime_type),
+            episode.duration,
+            episode.author,
+            episode.image,
+            episode.summary,
+            episode.explicit,
+            episode.episode_number,
+            episode.season,
+            episode.episode_type,
+            episode.block,
+            episode.season_name,
+            episode.episode_display,
+        ],
+    )?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+    let id = conn.last_insert_rowid();
+    insert_episode_extras(conn, id, episode)?;
+    Ok(Some(id))
+}
+
+// Records how far into an episode playback got. The duration is only overwritten when the
+// player actually knows it.
+pub fn save_position(
+    conn: &rusqlite::Connection,
+    id: i64,
+    position: i64,
+    duration: Option<i64>,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE episodes
+        SET position = ?2, duration = COALESCE(?3, duration), last_played = datetime('now')
+        WHERE id = ?1",
+        rusqlite::params![id, position, duration],
+    )?;
+    Ok(())
+}
+
+// Marking an episode (un)played also rewinds it, so the next play starts from the top. It
+// counts as a listen, like saving a position does, so it's when the episode was last played.
+pub fn set_played(
+    conn: &rusqlite::Connection,
+    id: i64,
+    played: bool,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE episodes
+        SET played = ?2, position = 0, last_played = datetime('now')
+        WHERE id = ?1",
+        rusqlite::params![id, played],
+    )?;
+    Ok(())
+}
+
+// Overwrites an episode's listening state with that of the given episode, as restoring a
+// backup does.
+pub fn save_playback_state(
+    conn: &rusqlite::Connection,
+    id: i64,
+    state: &podcast::Episode,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE episodes
+        SET played = ?2, position = ?3, last_played = ?4, starred = ?5, removed = ?6
+        WHERE id = ?1",
+        rusqlite::params![
+            id,
+            state.played,
+            state.position,
+            state.last_played,
+            state.starred,
+            state.removed
         ],
     )?;
     Ok(())
 }
 
+pub fn set_starred(
+    conn: &rusqlite::Connection,
+    id: i64,
+    starred: bool,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE episodes SET starred = ?2 WHERE id = ?1",
+        rusqlite::params![id, starred],
+    )?;
+    Ok(())
+}
+
+pub fn fetch_queue(conn: &rusqlite::Connection) -> Result<Vec<podcast::Episode>, rusqlite::Error> {
+    let mut ret = Vec::new();
+    let mut ep_stmt = conn.prepare(&format!(
+        "SELECT {} FROM episodes
+        JOIN queue ON queue.episode_id = episodes.id
+        ORDER BY queue.position",
+        EPISODE_COLUMNS
+    ))?;
+    let eps = ep_stmt.query_map(rusqlite::params![], episode_from_row)?;
+    for ep in eps {
+        ret.push(ep?);
+    }
+    Ok(ret)
+}
+
+// Appends an episode to the end of the queue. Queuing an episode twice leaves it where it is.
+pub fn queue_add(conn: &rusqlite::Connection, episode_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR IGNORE INTO queue (episode_id, position)
+        SELECT ?1, COALESCE(MAX(position), 0) + 1 FROM queue",
+        rusqlite::params![episode_id],
+    )?;
+    Ok(())
+}
+
+pub fn queue_remove(conn: &rusqlite::Connection, episode_id: i64) -> Result<bool, rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    let removed = tx.execute(
+        "DELETE FROM queue
+        WHERE episode_id = ?1",
+        rusqlite::params![episode_id],
+    )?;
+    renumber_queue(&tx)?;
+    tx.commit()?;
+    Ok(removed > 0)
+}
+
+// Moves a queued episode to the given 1-based position, clamped to the length of the queue.
+pub fn queue_move(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+    position: usize,
+) -> Result<bool, rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    let mut ids = queued_ids(&tx)?;
+    let from = match ids.iter().position(|id| *id == episode_id) {
+        Some(from) => from,
+        None => return Ok(false),
+    };
+    let id = ids.remove(from);
+    ids.insert(position.clamp(1, ids.len() + 1) - 1, id);
+    write_queue_order(&tx, &ids)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+pub fn queue_clear(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM queue", ())?;
+    Ok(())
+}
+
+// Takes the episode at the head of the queue off it.
+pub fn queue_pop(conn: &rusqlite::Connection) -> Result<Option<podcast::Episode>, rusqlite::Error> {
+    let next = match queued_ids(conn)?.first() {
+        Some(id) => fetch_episode(conn, *id)?,
+        None => return Ok(None),
+    };
+    queue_remove(conn, next.id)?;
+    Ok(Some(next))
+}
+
+fn queued_ids(conn: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT episode_id FROM queue ORDER BY position")?;
+    let ids = stmt.query_map(rusqlite::params![], |row| row.get(0))?;
+    ids.collect()
+}
+
+fn renumber_queue(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    write_queue_order(conn, &queued_ids(conn)?)
+}
+
+fn write_queue_order(conn: &rusqlite::Connection, ids: &[i64]) -> Result<(), rusqlite::Error> {
+    for (i, id) in ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE queue SET position = ?2 WHERE episode_id = ?1",
+            rusqlite::params![id, i + 1],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn record_download(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+    path: &str,
+    size: u64,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO downloads (episode_id, path, size, downloaded_at)
+        VALUES (?1, ?2, ?3, datetime('now'))",
+        rusqlite::params![episode_id, path, size as i64],
+    )?;
+    Ok(())
+}
+
+// Every downloaded episode, oldest download first.
+pub fn fetch_downloads(
+    conn: &rusqlite::Connection,
+) -> Result<Vec<podcast::DownloadedFile>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, episodes.podcast_id, downloads.size, downloads.downloaded_at FROM downloads
+        JOIN episodes ON episodes.id = downloads.episode_id
+        ORDER BY downloads.downloaded_at, downloads.episode_id",
+        EPISODE_COLUMNS
+    ))?;
+    let downloads = stmt.query_map((), |row| {
+        Ok(podcast::DownloadedFile {
+            episode: episode_from_row(row)?,
+            podcast_id: row.get(26)?,
+            size: row.get::<_, i64>(27)? as u64,
+            downloaded_at: row.get(28)?,
+        })
+    })?;
+    downloads.collect()
+}
+
+pub fn remove_download(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM downloads WHERE episode_id = ?1",
+        rusqlite::params![episode_id],
+    )?;
+    Ok(())
+}
+
+pub fn fetch_policy(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+) -> Result<podcast::Policy, rusqlite::Error> {
+    Ok(conn
+        .query_row(
+            "SELECT auto_download, keep_last, max_age_days, delete_played, speed FROM policies
+            WHERE podcast_id = ?1",
+            rusqlite::params![podcast_id],
+            |row| {
+                Ok(podcast::Policy {
+                    auto_download: row.get(0)?,
+                    keep_last: row.get(1)?,
+                    max_age_days: row.get(2)?,
+                    delete_played: row.get(3)?,
+                    speed: row.get(4)?,
+                })
+            },
+        )
+        .optional()?
+        .unwrap_or_default())
+}
+
+pub fn save_policy(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+    policy: &podcast::Policy,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO policies
+            (podcast_id, auto_download, keep_last, max_age_days, delete_played, speed)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            podcast_id,
+            policy.auto_download,
+            policy.keep_last,
+            policy.max_age_days,
+            policy.delete_played,
+            policy.speed
+        ],
+    )?;
+    Ok(())
+}
+
+// The settings key for the most space downloads may take up, in bytes.
+pub const DISK_QUOTA: &str = "disk_quota";
+
+pub fn fetch_setting(
+    conn: &rusqlite::Connection,
+    key: &str,
+) -> Result<Option<String>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        rusqlite::params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+// Stores a setting, or forgets it when given None.
+pub fn save_setting(
+    conn: &rusqlite::Connection,
+    key: &str,
+    value: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    match value {
+        Some(value) => conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            rusqlite::params![key, value],
+        )?,
+        None => conn.execute(
+            "DELETE FROM settings WHERE key = ?1",
+            rusqlite::params![key],
+        )?,
+    };
+    Ok(())
+}
+
+pub fn fetch_settings(
+    conn: &rusqlite::Connection,
+) -> Result<Vec<(String, String)>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key")?;
+    let settings = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    settings.collect()
+}
+
+// The episode downloaded to the given path, if any.
+pub fn fetch_download_owner(
+    conn: &rusqlite::Connection,
+    path: &str,
+) -> Result<Option<i64>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT episode_id FROM downloads WHERE path = ?1",
+        rusqlite::params![path],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn fetch_podcast_of_episode(
+    conn: &rusqlite::Connection,
+    episode_id: i64,
+) -> Result<podcast::Podcast, rusqlite::Error> {
+    let podcast_id: i64 = conn.query_row(
+        "SELECT podcast_id FROM episodes WHERE id = ?1",
+        rusqlite::params![episode_id],
+        |row| row.get(0),
+    )?;
+    fetch_podcast(conn, podcast_id)
+}
+
 pub fn remove_podcast(conn: &rusqlite::Connection, id: i64) -> Result<(), rusqlite::Error> {
     conn.execute(
         "DELETE FROM podcasts
         WHERE id = ?1",
         rusqlite::params![id],
     )?;
+    for table in [
+        "queue",
+        "chapters",
+        "transcripts",
+        "persons",
+        "soundbites",
+        "episode_revisions",
+        "downloads",
+    ] {
+        conn.execute(
+            &format!(
+                "DELETE FROM {}
+                WHERE episode_id IN (SELECT id FROM episodes WHERE podcast_id = ?1)",
+                table
+            ),
+            rusqlite::params![id],
+        )?;
+    }
+    for table in ["funding", "policies", "podcast_tags"] {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE podcast_id = ?1", table),
+            rusqlite::params![id],
+        )?;
+    }
     conn.execute(
         "DELETE FROM episodes
         WHERE podcast_id = ?1",
@@ -273,114 +1289,124 @@ pub fn remove_podcast(conn: &rusqlite::Connection, id: i64) -> Result<(), rusqli
     Ok(())
 }
 
-pub fn search_podcasts(
+// Tags a podcast, creating the tag if it's new. Tags match case-insensitively, and an
+// existing tag keeps the case it was first given in. Returns false if the podcast already
+// had the tag.
+pub fn tag_podcast(
     conn: &rusqlite::Connection,
-    term: String,
-) -> Result<Vec<podcast::Podcast>, rusqlite::Error> {
-    let mut search_query = conn.prepare(
-        "SELECT id, title, description, rss_url, link, language, pub_date, last_build_date
-        FROM podcasts
-        WHERE title LIKE ?1 OR description LIKE ?1",
+    podcast_id: i64,
+    tag: &str,
+) -> Result<bool, rusqlite::Error> {
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+        rusqlite::params![tag],
     )?;
-    let mut ret = Vec::new();
-    let pods = search_query.query_map(rusqlite::params!["%".to_string() + &term + "%"], |row| {
-        Ok(podcast::Podcast {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: row.get(2)?,
-            rss_url: row.get(3)?,
-            link: row.get(4)?,
-            language: row.get(5)?,
-            pub_date: row.get(6)?,
-            last_build_date: row.get(7)?,
-            episodes: Vec::new(),
-        })
-    })?;
-    for pod in pods {
-        ret.push(pod?);
-    }
-    Ok(ret)
+    let added = conn.execute(
+        "INSERT OR IGNORE INTO podcast_tags (podcast_id, tag_id)
+        SELECT ?1, id FROM tags WHERE name = ?2",
+        rusqlite::params![podcast_id, tag],
+    )?;
+    Ok(added > 0)
 }
 
-pub fn search_episodes(
+// Takes a tag off a podcast, and drops the tag altogether once nothing has it. Returns
+// false if the podcast didn't have the tag.
+pub fn untag_podcast(
     conn: &rusqlite::Connection,
-    term: String,
-    id: i64,
-) -> Result<Vec<podcast::Episode>, rusqlite::Error> {
-    match id {
-        0 => search_episodes_any(conn, term),
-        _ => search_episodes_of_podcast(conn, term, id),
+    podcast_id: i64,
+    tag: &str,
+) -> Result<bool, rusqlite::Error> {
+    let removed = conn.execute(
+        "DELETE FROM podcast_tags
+        WHERE podcast_id = ?1 AND tag_id IN (SELECT id FROM tags WHERE name = ?2)",
+        rusqlite::params![podcast_id, tag],
+    )?;
+    conn.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM podcast_tags)",
+        (),
+    )?;
+    Ok(removed > 0)
+}
+
+// Gives a podcast exactly the given tags, dropping any it had that aren't among them and
+// any tag that nothing has anymore.
+pub fn set_tags(
+    conn: &rusqlite::Connection,
+    podcast_id: i64,
+    tags: &[String],
+) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM podcast_tags WHERE podcast_id = ?1",
+        rusqlite::params![podcast_id],
+    )?;
+    for tag in tags {
+        tag_podcast(&tx, podcast_id, tag)?;
     }
+    tx.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM podcast_tags)",
+        (),
+    )?;
+    tx.commit()
+}
+
+// Every tag in use, with how many podcasts have it, in alphabetical order.
+pub fn fetch_tags(conn: &rusqlite::Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT tags.name, COUNT(*) FROM tags
+        JOIN podcast_tags ON podcast_tags.tag_id = tags.id
+        GROUP BY tags.id
+        ORDER BY tags.name",
+    )?;
+    let tags = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    tags.collect()
 }
 
-pub fn search_episodes_any(
+// Searches podcasts with an FTS5 query, best matches first. Matched terms in the snippet
+// are wrapped in the given pair of markers.
+pub fn search_podcasts(
     conn: &rusqlite::Connection,
-    term: String,
-) -> Result<Vec<podcast::Episode>, rusqlite::Error> {
-    let query_string = "SELECT id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type
-        FROM episodes
-        WHERE title LIKE ?1 OR description LIKE ?1";
-    let params = rusqlite::params!["%".to_string() + &term + "%"];
-    let mut search_query = conn.prepare(query_string)?;
-    let mut ret = Vec::new();
-    let eps = search_query.query_map(params, |row| {
-        Ok(podcast::Episode {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            guid: row.get(2)?,
-            description: row.get(3)?,
-            pub_date: row.get(4)?,
-            link: row.get(5)?,
-            enclosure: match row.get(6)? {
-                Some(url) => Some(podcast::Enclosure {
-                    url,
-                    length: row.get(7)?,
-                    mime_type: row.get(8)?,
-                }),
-                None => None,
-            },
+    query: &str,
+    highlight: (&str, &str),
+) -> Result<Vec<podcast::SearchHit<podcast::Podcast>>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, snippet(podcasts_fts, -1, ?2, ?3, '…', 12) FROM podcasts
+        JOIN podcasts_fts ON podcasts_fts.rowid = podcasts.id
+        WHERE podcasts_fts MATCH ?1
+        ORDER BY podcasts_fts.rank",
+        PODCAST_COLUMNS
+    ))?;
+    let hits = stmt.query_map(rusqlite::params![query, highlight.0, highlight.1], |row| {
+        Ok(podcast::SearchHit {
+            item: podcast_from_row(row)?,
+            snippet: row.get(16)?,
         })
     })?;
-    for ep in eps {
-        ret.push(ep?);
-    }
-    Ok(ret)
+    hits.collect()
 }
 
-pub fn search_episodes_of_podcast(
+// Searches episodes with an FTS5 query, optionally within one podcast, best matches first.
+pub fn search_episodes(
     conn: &rusqlite::Connection,
-    term: String,
-    id: i64,
-) -> Result<Vec<podcast::Episode>, rusqlite::Error> {
-    let mut search_query = conn.prepare(
-        "SELECT id, title, guid, description, pub_date, link, enclosure_url, enclosure_length, enclosure_mime_type
-        FROM episodes
-        WHERE (title LIKE ?1 OR description LIKE ?1) AND podcast_id = ?2",
-    )?;
-    let mut ret = Vec::new();
-    let eps = search_query.query_map(
-        rusqlite::params!["%".to_string() + &term + "%", id],
+    query: &str,
+    podcast_id: Option<i64>,
+    highlight: (&str, &str),
+) -> Result<Vec<podcast::SearchHit<podcast::Episode>>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, snippet(episodes_fts, -1, ?3, ?4, '…', 12) FROM episodes
+        JOIN episodes_fts ON episodes_fts.rowid = episodes.id
+        WHERE episodes_fts MATCH ?1 AND (?2 IS NULL OR episodes.podcast_id = ?2)
+        ORDER BY episodes_fts.rank",
+        EPISODE_COLUMNS
+    ))?;
+    let hits = stmt.query_map(
+        rusqlite::params![query, podcast_id, highlight.0, highlight.1],
         |row| {
-            Ok(podcast::Episode {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                guid: row.get(2)?,
-                description: row.get(3)?,
-                pub_date: row.get(4)?,
-                link: row.get(5)?,
-                enclosure: match row.get(6)? {
-                    Some(url) => Some(podcast::Enclosure {
-                        url,
-                        length: row.get(7)?,
-                        mime_type: row.get(8)?,
-                    }),
-                    None => None,
-                },
+            Ok(podcast::SearchHit {
+                item: episode_from_row(row)?,
+                snippet: row.get(26)?,
             })
         },
     )?;
-    for ep in eps {
-        ret.push(ep?);
-    }
-    Ok(ret)
+    hits.collect()
 }
@@ -0,0 +1,151 @@
+use crate::db;
+use crate::error::Error;
+use crate::podcast;
+use crate::template;
+use anyhow::Result;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+pub struct Download {
+    pub path: PathBuf,
+    pub size: u64,
+    // The size the feed claimed for the enclosure, when it differs from what we got. Feeds
+    // with dynamically inserted ads rarely get this right, so it's a warning, not an error.
+    pub expected_size: Option<u64>,
+}
+
+// Streams an episode's enclosure to `dest` and records it as downloaded. Data goes to a
+// `.part` file next to `dest` until it's complete, and an interrupted download picks up
+// from there with a Range request the next time round. `progress` is called with the bytes
+// received so far and the total, when the server says.
+pub fn download(
+    conn: &rusqlite::Connection,
+    ep: &podcast::Episode,
+    dest: &Path,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Download> {
+    let enclosure = ep
+        .enclosure
+        .as_ref()
+        .ok_or_else(|| Error::NotFound(format!("{} has no enclosure to download.", ep.title)))?;
+    let expected = enclosure
+        .length
+        .as_deref()
+        .and_then(|l| l.trim().parse::<u64>().ok())
+        .filter(|l| *l > 0);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = part_path(dest);
+    let mut done = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+
+    // Episodes can take far longer than any sensible overall timeout to come down.
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()?;
+    let mut request = client.get(&enclosure.url);
+    if done > 0 {
+        request = request.header(RANGE, format!("bytes={}-", done));
+    }
+    let mut response = request.send()?;
+    // A server refusing the range means the partial file already has everything.
+    if !(done > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE) {
+        response = response.error_for_status()?;
+        let mut file = if response.status() == StatusCode::PARTIAL_CONTENT {
+            fs::OpenOptions::new().append(true).open(&part)?
+        } else {
+            // The server ignored the range, so start over.
+            done = 0;
+            fs::File::create(&part)?
+        };
+        let complete_at = response.content_length().map(|l| l + done);
+        let total = complete_at.or(expected);
+        progress(done, total);
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let n = response
+                .read(&mut buf)
+                .map_err(|e| interrupted(ep, done, &e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            done += n as u64;
+            progress(done, total);
+        }
+        file.flush()?;
+        if let Some(complete_at) = complete_at {
+            if done < complete_at {
+                return Err(interrupted(ep, done, "connection closed early"));
+            }
+        }
+    }
+    fs::rename(&part, dest)?;
+    let path = absolute(dest)?;
+    let size = fs::metadata(&path)?.len();
+    db::record_download(conn, ep.id, &path.to_string_lossy(), size)?;
+    Ok(Download {
+        path,
+        size,
+        expected_size: expected.filter(|e| *e != size),
+    })
+}
+
+// Works out where an episode goes under `root`. When another episode already has that
+// path, the episode's id is added to the name, so the same episode always lands in the same
+// place however many runs it takes.
+pub fn destination(
+    conn: &rusqlite::Connection,
+    root: &Path,
+    template: &template::Template,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+) -> Result<PathBuf> {
+    let path = absolute(&root.join(template.render(pod, ep)))?;
+    let taken = match db::fetch_download_owner(conn, &path.to_string_lossy())? {
+        Some(owner) => owner != ep.id,
+        None => path.exists(),
+    };
+    if !taken {
+        return Ok(path);
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, ep.id, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, ep.id),
+    };
+    Ok(path.with_file_name(name))
+}
+
+// Downloads are recorded by absolute path so the file can be found from any directory.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+fn interrupted(ep: &podcast::Episode, done: u64, reason: &str) -> anyhow::Error {
+    Error::Network(format!(
+        "Download of {} stopped after {} bytes ({}); run it again to resume.",
+        ep.title, done, reason
+    ))
+    .into()
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
@@ -0,0 +1,139 @@
+// The kinds of failure dipper tells apart. Each has its own exit code, so scripts and cron
+// jobs can react to a missing podcast differently from a network outage. Anything that
+// isn't one of these exits with 1, and clap exits with 2 on bad arguments.
+#[derive(Debug)]
+pub enum Error {
+    // There's no podcast, episode or file by that name or id.
+    NotFound(String),
+    // A feed, enclosure or image couldn't be fetched.
+    Network(String),
+    // A feed, OPML file, config file or filename template doesn't make sense.
+    Parse(String),
+    // The database couldn't be opened, or is locked or damaged.
+    Database(String),
+    // Playback failed.
+    Player(String),
+}
+
+// Shown at the end of `--help`.
+pub const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  any other error
+  2  bad command line arguments
+  3  not found: no podcast, episode or file by that id or name
+  4  network: a feed or enclosure couldn't be fetched
+  5  parse: a feed, OPML file, config file or template doesn't make sense
+  6  database: the database couldn't be opened, or is locked or damaged
+  7  player: playback failed";
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotFound(_) => 3,
+            Error::Network(_) => 4,
+            Error::Parse(_) => 5,
+            Error::Database(_) => 6,
+            Error::Player(_) => 7,
+        }
+    }
+
+    // The kind as it appears in JSON error output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::Network(_) => "network",
+            Error::Parse(_) => "parse",
+            Error::Database(_) => "database",
+            Error::Player(_) => "player",
+        }
+    }
+
+    // An error of the same kind, saying something else.
+    pub fn reworded(&self, message: String) -> Error {
+        match self {
+            Error::NotFound(_) => Error::NotFound(message),
+            Error::Network(_) => Error::Network(message),
+            Error::Parse(_) => Error::Parse(message),
+            Error::Database(_) => Error::Database(message),
+            Error::Player(_) => Error::Player(message),
+        }
+    }
+
+    // Works out what kind of error `e` is, from the first thing in its chain that says.
+    // Errors straight from a library get their kind named in the message, since "database
+    // is locked" makes more sense as "Database error: database is locked".
+    pub fn classify(e: &anyhow::Error) -> Option<Error> {
+        for (depth, cause) in e.chain().enumerate() {
+            let message = |kind: &str| {
+                if depth == 0 {
+                    format!("{} error: {}", kind, e)
+                } else {
+                    e.to_string()
+                }
+            };
+            if let Some(ours) = cause.downcast_ref::<Error>() {
+                return Some(ours.reworded(e.to_string()));
+            }
+            if let Some(sqlite) = cause.downcast_ref::<rusqlite::Error>() {
+                return Some(match sqlite {
+                    rusqlite::Error::QueryReturnedNoRows => Error::NotFound(message("Lookup")),
+                    _ => Error::Database(message("Database")),
+                });
+            }
+            if cause.is::<reqwest::Error>() {
+                return Some(Error::Network(message("Network")));
+            }
+            if cause.is::<rss::Error>()
+                || cause.is::<atom_syndication::Error>()
+                || cause.is::<serde_json::Error>()
+                || cause.is::<opml::Error>()
+                || cause.is::<toml::de::Error>()
+            {
+                return Some(Error::Parse(message("Parse")));
+            }
+            if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+                if io.kind() == std::io::ErrorKind::NotFound {
+                    return Some(Error::NotFound(e.to_string()));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotFound(message)
+            | Error::Network(message)
+            | Error::Parse(message)
+            | Error::Database(message)
+            | Error::Player(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// For lookups by id, where no rows means there's nothing by that id rather than that
+// something went wrong.
+pub trait OrNotFound<T> {
+    fn or_not_found(self, what: impl FnOnce() -> String) -> anyhow::Result<T>;
+}
+
+impl<T> OrNotFound<T> for rusqlite::Result<T> {
+    fn or_not_found(self, what: impl FnOnce() -> String) -> anyhow::Result<T> {
+        match self {
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::NotFound(what()).into()),
+            result => Ok(result?),
+        }
+    }
+}
+
+pub fn no_podcast(id: i64) -> String {
+    format!("No podcast with id {}.", id)
+}
+
+pub fn no_episode(id: i64) -> String {
+    format!("No episode with id {}.", id)
+}
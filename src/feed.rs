@@ -1,9 +1,103 @@
 use crate::podcast;
-use bytes::Bytes;
-use chrono::DateTime;
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone, Utc,
+};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use rss::extension::Extension;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 
-pub fn fetch_rss(url: &str) -> reqwest::Result<String> {
-    reqwest::blocking::get(url)?.text()
+const PODCAST_NAMESPACE: &str = "https://podcastindex.org/namespace/1.0";
+
+pub enum Fetched {
+    // The server answered 304, or sent back exactly the body we parsed last time.
+    Unchanged(podcast::FeedCache),
+    Modified(String, podcast::FeedCache),
+}
+
+// Fetches a feed, sending the validators from the last fetch so the server can answer
+// 304 Not Modified. The returned cache should be stored for the next fetch either way.
+pub fn fetch_rss(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    cache: &podcast::FeedCache,
+) -> reqwest::Result<Fetched> {
+    let mut request = client.get(url);
+    if let Some(etag) = &cache.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &cache.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send()?.error_for_status()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::Unchanged(cache.clone()));
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let body = response.text()?;
+    let fresh = podcast::FeedCache {
+        etag,
+        last_modified,
+        content_hash: Some(format!("{:x}", Sha256::digest(body.as_bytes()))),
+    };
+    if fresh.content_hash == cache.content_hash {
+        return Ok(Fetched::Unchanged(fresh));
+    }
+    Ok(Fetched::Modified(body, fresh))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    JsonFeed,
+}
+
+// Works out what kind of feed a body holds from its first significant character and, for
+// XML, the name of the root element. Servers label feeds too inconsistently for the
+// Content-Type to be worth trusting.
+pub fn sniff_format(body: &str) -> FeedFormat {
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+    if body.starts_with('{') {
+        return FeedFormat::JsonFeed;
+    }
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with('?') || rest.starts_with('!') {
+            continue;
+        }
+        let name: String = rest
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '>' && *c != '/')
+            .collect();
+        let local = name.rsplit(':').next().unwrap_or_default();
+        if local == "feed" {
+            return FeedFormat::Atom;
+        }
+        break;
+    }
+    FeedFormat::Rss
+}
+
+// Parses an RSS, Atom or JSON Feed document into a podcast.
+pub fn parse_feed(url: &str, body: &str) -> Result<podcast::Podcast> {
+    match sniff_format(body) {
+        FeedFormat::Rss => Ok(parse_rss(url, body)?),
+        FeedFormat::Atom => parse_atom(url, body),
+        FeedFormat::JsonFeed => parse_json_feed(url, body),
+    }
 }
 
 pub fn parse_rss(url: &str, rss: &str) -> Result<podcast::Podcast, rss::Error> {
@@ -18,53 +112,560 @@ pub fn parse_rss(url: &str, rss: &str) -> Result<podcast::Podcast, rss::Error> {
     podcast.language = extract_podfield(channel.language());
     podcast.pub_date = fix_date(channel.pub_date());
     podcast.last_build_date = fix_date(channel.last_build_date());
+    if let Some(itunes) = channel.itunes_ext() {
+        parse_itunes_channel(&mut podcast, itunes);
+    }
+    let prefix = podcast_prefix(&channel);
+    if let Some(tags) = channel.extensions().get(&prefix) {
+        parse_podcast_channel(&mut podcast, tags);
+    }
     for item in channel.items() {
-        if let Ok(item) = parse_item(item) {
-            podcast.episodes.push(item);
-        }
+        podcast.episodes.push(parse_item(item, &prefix));
     }
     Ok(podcast)
 }
 
-pub fn fetch_enclosure(enclosure: &podcast::Enclosure) -> reqwest::Result<Bytes> {
-    reqwest::blocking::get(&enclosure.url)?.bytes()
+pub fn parse_atom(url: &str, atom: &str) -> Result<podcast::Podcast> {
+    let feed = atom_syndication::Feed::read_from(atom.as_bytes())?;
+    let mut podcast = podcast::Podcast::new(
+        feed.title().value.clone(),
+        feed.subtitle().map(|s| s.value.clone()).unwrap_or_default(),
+        url.to_string(),
+    );
+    podcast.link = alternate_link(feed.links());
+    podcast.language = extract_podfield(feed.lang());
+    podcast.last_build_date = Some(format_date(feed.updated()));
+    podcast.author = feed.authors().first().map(|a| a.name().to_string());
+    podcast.image = extract_podfield(feed.logo().or(feed.icon()));
+    podcast.categories = feed
+        .categories()
+        .iter()
+        .map(|c| c.label().unwrap_or(c.term()).to_string())
+        .collect();
+    for entry in feed.entries() {
+        podcast.episodes.push(parse_atom_entry(entry));
+    }
+    Ok(podcast)
 }
 
-fn extract_podfield(field: Option<&str>) -> Option<String> {
-    field.map(|s| s.to_string())
+fn parse_atom_entry(entry: &atom_syndication::Entry) -> podcast::Episode {
+    let description = entry
+        .summary()
+        .map(|s| s.value.clone())
+        .or_else(|| {
+            entry
+                .content()
+                .and_then(|c| c.value())
+                .map(|v| v.to_string())
+        })
+        .unwrap_or_default();
+    let mut episode = podcast::Episode::new(
+        entry.title().value.clone(),
+        entry.id().trim().to_string(),
+        description,
+    );
+    episode.pub_date = Some(format_date(entry.published().unwrap_or(entry.updated())));
+    episode.link = alternate_link(entry.links());
+    episode.author = entry.authors().first().map(|a| a.name().to_string());
+    episode.enclosure = entry
+        .links()
+        .iter()
+        .find(|link| link.rel() == "enclosure")
+        .map(|link| podcast::Enclosure {
+            url: link.href().to_string(),
+            length: extract_podfield(link.length()),
+            mime_type: extract_podfield(link.mime_type()),
+        });
+    fill_missing_guid(&mut episode);
+    episode
 }
 
-fn parse_item(item: &rss::Item) -> Result<podcast::Episode, ()> {
-    if item.guid().is_none() {
-        return Err(());
+fn alternate_link(links: &[atom_syndication::Link]) -> Option<String> {
+    links
+        .iter()
+        .find(|link| link.rel() == "alternate")
+        .map(|link| link.href().to_string())
+}
+
+// https://www.jsonfeed.org/version/1.1/, which also covers the fields 1.0 feeds use.
+#[derive(Deserialize)]
+struct JsonFeed {
+    version: String,
+    title: String,
+    home_page_url: Option<String>,
+    description: Option<String>,
+    icon: Option<String>,
+    favicon: Option<String>,
+    language: Option<String>,
+    author: Option<JsonAuthor>,
+    #[serde(default)]
+    authors: Vec<JsonAuthor>,
+    #[serde(default)]
+    items: Vec<JsonItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonAuthor {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+    // Meant to be a string, but plenty of feeds use numbers.
+    #[serde(default)]
+    id: serde_json::Value,
+    url: Option<String>,
+    title: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    summary: Option<String>,
+    image: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    author: Option<JsonAuthor>,
+    #[serde(default)]
+    authors: Vec<JsonAuthor>,
+    #[serde(default)]
+    attachments: Vec<JsonAttachment>,
+}
+
+#[derive(Deserialize)]
+struct JsonAttachment {
+    url: String,
+    mime_type: Option<String>,
+    size_in_bytes: Option<u64>,
+    duration_in_seconds: Option<f64>,
+}
+
+pub fn parse_json_feed(url: &str, json: &str) -> Result<podcast::Podcast> {
+    let feed: JsonFeed = serde_json::from_str(json)?;
+    if !feed.version.starts_with("https://jsonfeed.org/version/") {
+        return Err(anyhow!("Unknown JSON Feed version {}.", feed.version));
+    }
+    let mut podcast = podcast::Podcast::new(
+        feed.title,
+        feed.description.unwrap_or_default(),
+        url.to_string(),
+    );
+    podcast.link = feed.home_page_url;
+    podcast.language = feed.language;
+    podcast.image = feed.icon.or(feed.favicon);
+    podcast.author = json_author(feed.authors, feed.author);
+    for item in feed.items {
+        podcast.episodes.push(parse_json_item(item));
+    }
+    Ok(podcast)
+}
+
+fn parse_json_item(item: JsonItem) -> podcast::Episode {
+    let guid = match item.id {
+        serde_json::Value::String(id) => id.trim().to_string(),
+        serde_json::Value::Null => String::new(),
+        id => id.to_string(),
+    };
+    let description = item
+        .content_text
+        .or(item.content_html)
+        .or(item.summary.clone())
+        .unwrap_or_default();
+    let mut episode = podcast::Episode::new(item.title.unwrap_or_default(), guid, description);
+    episode.pub_date = fix_date(
+        item.date_published
+            .as_deref()
+            .or(item.date_modified.as_deref()),
+    );
+    episode.link = item.url;
+    episode.image = item.image;
+    episode.summary = item.summary;
+    episode.author = json_author(item.authors, item.author);
+    // Prefer audio when an item offers several attachments.
+    let mut attachments = item.attachments;
+    let audio = attachments.iter().position(|a| {
+        a.mime_type
+            .as_deref()
+            .is_some_and(|t| t.starts_with("audio/"))
+    });
+    if !attachments.is_empty() {
+        let attachment = attachments.swap_remove(audio.unwrap_or(0));
+        episode.duration = attachment.duration_in_seconds.map(|d| d as i64);
+        episode.enclosure = Some(podcast::Enclosure {
+            url: attachment.url,
+            length: attachment.size_in_bytes.map(|s| s.to_string()),
+            mime_type: attachment.mime_type,
+        });
     }
-    if item.title().is_none() {
-        return Err(());
+    fill_missing_guid(&mut episode);
+    episode
+}
+
+// JSON Feed 1.1 replaced the single author with a list.
+fn json_author(authors: Vec<JsonAuthor>, author: Option<JsonAuthor>) -> Option<String> {
+    authors.into_iter().chain(author).find_map(|a| a.name)
+}
+
+// Titles are optional in RSS as long as there's a description. An episode still needs
+// something to be listed by, so it gets the start of its description, or failing that its
+// date.
+fn fallback_title(episode: &podcast::Episode) -> String {
+    const MAX_CHARS: usize = 60;
+    let text = podcast::plain_text(&episode.description);
+    if !text.is_empty() {
+        return match text.char_indices().nth(MAX_CHARS) {
+            Some((end, _)) => format!("{}…", text[..end].trim_end()),
+            None => text,
+        };
     }
-    if item.description().is_none() {
-        return Err(());
+    match &episode.pub_date {
+        Some(date) => format!("Episode of {}", date.get(..10).unwrap_or(date)),
+        None => "Untitled episode".to_string(),
     }
+}
+
+fn extract_podfield(field: Option<&str>) -> Option<String> {
+    field.map(|s| s.to_string())
+}
+
+fn parse_item(item: &rss::Item, podcast_prefix: &str) -> podcast::Episode {
     let mut episode = podcast::Episode::new(
-        item.title().unwrap().to_string(),
-        item.guid().unwrap().value().to_string(),
+        item.title().unwrap_or_default().to_string(),
+        item.guid()
+            .map(|g| g.value().trim().to_string())
+            .unwrap_or_default(),
         item.description().unwrap_or_default().to_string(),
     );
     episode.pub_date = fix_date(item.pub_date());
+    if episode.title.trim().is_empty() {
+        episode.title = fallback_title(&episode);
+    }
     episode.link = extract_podfield(item.link());
     episode.enclosure = item.enclosure().map(|enc| podcast::Enclosure {
         url: enc.url().to_string(),
         length: Some(enc.length().to_string()),
         mime_type: Some(enc.mime_type().to_string()),
     });
-    Ok(episode)
+    if let Some(itunes) = item.itunes_ext() {
+        parse_itunes_item(&mut episode, itunes);
+    }
+    if let Some(tags) = item.extensions().get(podcast_prefix) {
+        parse_podcast_item(&mut episode, tags);
+    }
+    fill_missing_guid(&mut episode);
+    episode
+}
+
+// Gives an episode whose feed didn't identify it a guid that stays the same from one fetch
+// to the next: its enclosure URL when it has one, otherwise a hash of its title and date.
+fn fill_missing_guid(episode: &mut podcast::Episode) {
+    if !episode.guid.is_empty() {
+        return;
+    }
+    episode.guid = match &episode.enclosure {
+        Some(enclosure) => enclosure.url.clone(),
+        None => {
+            let key = format!(
+                "{}\n{}",
+                episode.title,
+                episode.pub_date.as_deref().unwrap_or_default()
+            );
+            format!("dipper:{:x}", Sha256::digest(key.as_bytes()))
+        }
+    };
+}
+
+fn parse_itunes_channel(
+    podcast: &mut podcast::Podcast,
+    itunes: &rss::extension::itunes::ITunesChannelExtension,
+) {
+    podcast.author = extract_podfield(itunes.author());
+    podcast.image = extract_podfield(itunes.image());
+    podcast.summary = extract_podfield(itunes.summary());
+    podcast.explicit = itunes.explicit().and_then(parse_explicit);
+    podcast.block = itunes.block().map(parse_yes).unwrap_or(false);
+    for category in itunes.categories() {
+        match category.subcategory() {
+            Some(sub) => podcast
+                .categories
+                .push(format!("{}/{}", category.text(), sub.text())),
+            None => podcast.categories.push(category.text().to_string()),
+        }
+    }
+}
+
+fn parse_itunes_item(
+    episode: &mut podcast::Episode,
+    itunes: &rss::extension::itunes::ITunesItemExtension,
+) {
+    episode.duration = itunes.duration().and_then(parse_duration);
+    episode.episode_number = itunes.episode().and_then(|e| e.trim().parse().ok());
+    episode.season = itunes.season().and_then(|s| s.trim().parse().ok());
+    episode.episode_type = itunes.episode_type().map(|t| t.trim().to_lowercase());
+    episode.explicit = itunes.explicit().and_then(parse_explicit);
+    episode.author = extract_podfield(itunes.author());
+    episode.image = extract_podfield(itunes.image());
+    episode.summary = extract_podfield(itunes.summary());
+    episode.block = itunes.block().map(parse_yes).unwrap_or(false);
+}
+
+// The prefix the feed binds the Podcasting 2.0 namespace to, which is nearly always
+// "podcast".
+fn podcast_prefix(channel: &rss::Channel) -> String {
+    channel
+        .namespaces()
+        .iter()
+        .find(|(_, uri)| uri.trim_end_matches('/') == PODCAST_NAMESPACE)
+        .map(|(prefix, _)| prefix.clone())
+        .unwrap_or_else(|| "podcast".to_string())
+}
+
+fn podcast_tags<'a>(
+    tags: &'a BTreeMap<String, Vec<Extension>>,
+    name: &str,
+) -> impl Iterator<Item = &'a Extension> {
+    tags.get(name).into_iter().flatten()
+}
+
+fn tag_attr(tag: &Extension, name: &str) -> Option<String> {
+    tag.attrs().get(name).map(|v| v.to_string())
+}
+
+fn tag_value(tag: &Extension) -> Option<String> {
+    tag.value()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn parse_podcast_channel(podcast: &mut podcast::Podcast, tags: &BTreeMap<String, Vec<Extension>>) {
+    podcast.locked =
+        podcast_tags(tags, "locked").any(|tag| tag.value().map(parse_yes).unwrap_or(false));
+    for tag in podcast_tags(tags, "funding") {
+        if let Some(url) = tag_attr(tag, "url") {
+            podcast.funding.push(podcast::Funding {
+                text: tag_value(tag).unwrap_or_else(|| url.clone()),
+                url,
+            });
+        }
+    }
+}
+
+fn parse_podcast_item(episode: &mut podcast::Episode, tags: &BTreeMap<String, Vec<Extension>>) {
+    episode.chapters = podcast_tags(tags, "chapters").find_map(|tag| {
+        Some(podcast::Chapters {
+            url: tag_attr(tag, "url")?,
+            mime_type: tag_attr(tag, "type"),
+        })
+    });
+    episode.transcripts = podcast_tags(tags, "transcript")
+        .filter_map(|tag| {
+            Some(podcast::Transcript {
+                url: tag_attr(tag, "url")?,
+                mime_type: tag_attr(tag, "type")?,
+                language: tag_attr(tag, "language"),
+                rel: tag_attr(tag, "rel"),
+            })
+        })
+        .collect();
+    episode.persons = podcast_tags(tags, "person")
+        .filter_map(|tag| {
+            Some(podcast::Person {
+                name: tag_value(tag)?,
+                role: tag_attr(tag, "role").map(|r| r.to_lowercase()),
+                group: tag_attr(tag, "group").map(|g| g.to_lowercase()),
+                img: tag_attr(tag, "img"),
+                href: tag_attr(tag, "href"),
+            })
+        })
+        .collect();
+    episode.soundbites = podcast_tags(tags, "soundbite")
+        .filter_map(|tag| {
+            Some(podcast::Soundbite {
+                start: tag_attr(tag, "startTime")?.trim().parse().ok()?,
+                duration: tag_attr(tag, "duration")?.trim().parse().ok()?,
+                title: tag_value(tag),
+            })
+        })
+        .collect();
+    // itunes:season and itunes:episode win when both namespaces are present.
+    if let Some(tag) = podcast_tags(tags, "season").next() {
+        if episode.season.is_none() {
+            episode.season = tag_value(tag).and_then(|s| s.parse().ok());
+        }
+        episode.season_name = tag_attr(tag, "name");
+    }
+    if let Some(tag) = podcast_tags(tags, "episode").next() {
+        if episode.episode_number.is_none() {
+            episode.episode_number = tag_value(tag).and_then(|e| e.parse().ok());
+        }
+        episode.episode_display = tag_attr(tag, "display");
+    }
+}
+
+// itunes:explicit has been "yes"/"no", "explicit"/"clean" and "true"/"false" over the years.
+fn parse_explicit(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "yes" | "true" | "explicit" => Some(true),
+        "no" | "false" | "clean" => Some(false),
+        _ => None,
+    }
 }
 
+fn parse_yes(value: &str) -> bool {
+    value.trim().eq_ignore_ascii_case("yes")
+}
+
+// itunes:duration is either a plain number of seconds or [[HH:]MM:]SS.
+fn parse_duration(value: &str) -> Option<i64> {
+    let mut secs = 0;
+    for part in value.trim().split(':') {
+        let part: f64 = part.trim().parse().ok()?;
+        secs = secs * 60 + part as i64;
+    }
+    Some(secs)
+}
+
+// Normalises a feed date to an RFC 3339 UTC timestamp, so dates compare correctly as
+// strings. A date we can't make sense of is kept verbatim rather than thrown away.
 fn fix_date(date: Option<&str>) -> Option<String> {
-    match date {
-        Some(d) => {
-            let dt = DateTime::parse_from_rfc2822(d).unwrap();
-            Some(dt.format("%Y-%m-%d").to_string())
+    let d = date?.trim();
+    if d.is_empty() {
+        return None;
+    }
+    match parse_date(d) {
+        Some(dt) => Some(format_date(&dt)),
+        None => Some(d.to_string()),
+    }
+}
+
+fn format_date(dt: &DateTime<FixedOffset>) -> String {
+    dt.with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn parse_date(d: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(dt) = DateTime::parse_from_rfc2822(d) {
+        return Some(dt);
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(d) {
+        return Some(dt);
+    }
+    parse_loose_rfc2822(d).or_else(|| parse_loose_iso8601(d))
+}
+
+// RFC 2822 as feeds actually write it: weekdays that are missing, misspelled or wrong, full
+// month names, two-digit years, no seconds, and named time zones.
+fn parse_loose_rfc2822(d: &str) -> Option<DateTime<FixedOffset>> {
+    let rest = match d.split_once(',') {
+        Some((_, rest)) => rest,
+        None => d,
+    };
+    let mut tokens = rest
+        .split_whitespace()
+        .skip_while(|t| t.chars().all(|c| c.is_alphabetic() || c == '.'))
+        .peekable();
+    let day: u32 = tokens.next()?.trim_end_matches(',').parse().ok()?;
+    let month = parse_month(tokens.next()?)?;
+    let year = match tokens.next()?.trim_end_matches(',').parse::<i32>().ok()? {
+        y if y < 50 => y + 2000,
+        y if y < 100 => y + 1900,
+        y => y,
+    };
+    let time = match tokens.peek() {
+        Some(t) if t.contains(':') => parse_time(tokens.next()?)?,
+        _ => NaiveTime::from_hms_opt(0, 0, 0)?,
+    };
+    let offset = match tokens.next() {
+        Some(zone) => parse_zone(zone).unwrap_or(0),
+        None => 0,
+    };
+    let naive = NaiveDate::from_ymd_opt(year, month, day)?.and_time(time);
+    FixedOffset::east_opt(offset)?
+        .from_local_datetime(&naive)
+        .single()
+}
+
+// ISO 8601 dates without an explicit offset are taken to be UTC.
+fn parse_loose_iso8601(d: &str) -> Option<DateTime<FixedOffset>> {
+    for fmt in ["%Y-%m-%d %H:%M:%S%.f %z", "%Y-%m-%dT%H:%M:%S%.f%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(d, fmt) {
+            return Some(dt);
+        }
+    }
+    let d = d.trim_end_matches('Z');
+    for fmt in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(d, fmt) {
+            return Some(Utc.from_utc_datetime(&naive).fixed_offset());
         }
-        None => None,
     }
+    let naive = NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()?;
+    Some(
+        Utc.from_utc_datetime(&naive.and_hms_opt(0, 0, 0)?)
+            .fixed_offset(),
+    )
+}
+
+fn parse_month(m: &str) -> Option<u32> {
+    let m = m.trim_end_matches(['.', ',']).to_lowercase();
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    months
+        .iter()
+        .position(|name| m.starts_with(name))
+        .map(|i| i as u32 + 1)
+}
+
+fn parse_time(t: &str) -> Option<NaiveTime> {
+    let mut parts = t.split(':');
+    let h = parts.next()?.parse().ok()?;
+    let m = parts.next()?.parse().ok()?;
+    let s = match parts.next() {
+        Some(s) => s.split('.').next()?.parse().ok()?,
+        None => 0,
+    };
+    NaiveTime::from_hms_opt(h, m, s)
+}
+
+// Returns the zone's offset east of UTC in seconds.
+fn parse_zone(zone: &str) -> Option<i32> {
+    if let Some(sign) = match zone.chars().next()? {
+        '+' => Some(1),
+        '-' => Some(-1),
+        _ => None,
+    } {
+        let digits: String = zone[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+        let (h, m) = match digits.len() {
+            1 | 2 => (digits.parse::<i32>().ok()?, 0),
+            4 => (
+                digits[..2].parse::<i32>().ok()?,
+                digits[2..].parse::<i32>().ok()?,
+            ),
+            _ => return None,
+        };
+        return Some(sign * (h * 3600 + m * 60));
+    }
+    let hours = match zone.to_uppercase().as_str() {
+        "Z" | "UT" | "UTC" | "GMT" | "WET" => 0.0,
+        "BST" | "CET" | "WEST" => 1.0,
+        "CEST" | "EET" | "SAST" => 2.0,
+        "EEST" | "MSK" => 3.0,
+        "IST" => 5.5,
+        "JST" | "KST" => 9.0,
+        "AEST" => 10.0,
+        "AEDT" => 11.0,
+        "NZST" => 12.0,
+        "NZDT" => 13.0,
+        "HST" => -10.0,
+        "AKST" => -9.0,
+        "AKDT" | "PST" => -8.0,
+        "PDT" | "MST" => -7.0,
+        "MDT" | "CST" => -6.0,
+        "CDT" | "EST" => -5.0,
+        "EDT" => -4.0,
+        _ => return None,
+    };
+    Some((hours * 3600.0) as i32)
 }
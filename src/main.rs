@@ -1,8 +1,16 @@
 mod actions;
+mod backup;
 mod cli;
+mod config;
 mod db;
+mod download;
+mod error;
 mod feed;
+mod output;
+mod player;
 mod podcast;
+mod tag;
+mod template;
 mod tui;
 
 fn main() {
@@ -13,8 +21,17 @@ fn main() {
 fn test() {
     println!("Hello, world!");
     let testdb = db::init_db(&"test.db".to_string()).unwrap();
-    let test_rss: String = feed::fetch_rss("https://www.pipes.digital/feed/7N3mlbqy").unwrap();
-    let mut pod = feed::parse_rss("https://www.pipes.digital/feed/7N3mlbqy", &test_rss).unwrap();
+    let test_rss = match feed::fetch_rss(
+        &reqwest::blocking::Client::new(),
+        "https://www.pipes.digital/feed/7N3mlbqy",
+        &podcast::FeedCache::default(),
+    )
+    .unwrap()
+    {
+        feed::Fetched::Modified(rss, _) => rss,
+        feed::Fetched::Unchanged(_) => unreachable!(),
+    };
+    let mut pod = feed::parse_feed("https://www.pipes.digital/feed/7N3mlbqy", &test_rss).unwrap();
     println!("{}", test_rss);
     println!("{:?}", pod);
     let _ = db::insert_podcast(&testdb, &mut pod);
@@ -0,0 +1,381 @@
+use crate::actions;
+use crate::error::Error;
+use crate::podcast;
+use serde::Serialize;
+
+// How results are printed, chosen with `--format`:
+//
+//   plain   the human-readable lines dipper has always printed
+//   table   the same results lined up in columns, with a header
+//   json    one JSON document per command: an array for lists, an object otherwise
+//   ndjson  one JSON object per line, streaming-friendly
+//
+// The records below are what the JSON formats carry. They're a stable interface: fields
+// may be added, but existing ones keep their names and meaning. Missing values are null.
+// Errors go to stderr as `{"error": "...", "kind": "..."}`, where the kind is "not_found",
+// "network", "parse", "database", "player" or "other", and the exit code says the same.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Plain,
+    Table,
+    Json,
+    Ndjson,
+}
+
+impl Format {
+    pub fn is_json(self) -> bool {
+        matches!(self, Format::Json | Format::Ndjson)
+    }
+}
+
+// A podcast, as printed by `list` and `search`.
+#[derive(Serialize)]
+pub struct PodcastRecord {
+    pub id: i64,
+    pub title: String,
+    pub description: String,
+    pub rss_url: String,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub language: Option<String>,
+    pub categories: Vec<String>,
+    pub tags: Vec<String>,
+    pub explicit: Option<bool>,
+    pub locked: bool,
+}
+
+impl From<&podcast::Podcast> for PodcastRecord {
+    fn from(pod: &podcast::Podcast) -> PodcastRecord {
+        PodcastRecord {
+            id: pod.id,
+            title: pod.title.clone(),
+            description: pod.description.clone(),
+            rss_url: pod.rss_url.clone(),
+            link: pod.link.clone(),
+            author: pod.author.clone(),
+            image: pod.image.clone(),
+            language: pod.language.clone(),
+            categories: pod.categories.clone(),
+            tags: pod.tags.clone(),
+            explicit: pod.explicit,
+            locked: pod.locked,
+        }
+    }
+}
+
+// An episode, as printed by `episodes` and `search --episodes`. Times are in seconds.
+#[derive(Serialize)]
+pub struct EpisodeRecord {
+    pub id: i64,
+    pub guid: String,
+    pub title: String,
+    pub description: String,
+    pub pub_date: Option<String>,
+    pub link: Option<String>,
+    pub enclosure_url: Option<String>,
+    pub enclosure_type: Option<String>,
+    pub duration: Option<i64>,
+    pub position: i64,
+    pub played: bool,
+    pub starred: bool,
+    pub removed: bool,
+    pub season: Option<i64>,
+    pub episode_number: Option<i64>,
+    pub episode_type: Option<String>,
+    pub explicit: Option<bool>,
+    pub download_path: Option<String>,
+}
+
+impl From<&podcast::Episode> for EpisodeRecord {
+    fn from(ep: &podcast::Episode) -> EpisodeRecord {
+        EpisodeRecord {
+            id: ep.id,
+            guid: ep.guid.clone(),
+            title: ep.title.clone(),
+            description: ep.description.clone(),
+            pub_date: ep.pub_date.clone(),
+            link: ep.link.clone(),
+            enclosure_url: ep.enclosure.as_ref().map(|e| e.url.clone()),
+            enclosure_type: ep.enclosure.as_ref().and_then(|e| e.mime_type.clone()),
+            duration: ep.duration,
+            position: ep.position,
+            played: ep.played,
+            starred: ep.starred,
+            removed: ep.removed,
+            season: ep.season,
+            episode_number: ep.episode_number,
+            episode_type: ep.episode_type.clone(),
+            explicit: ep.explicit,
+            download_path: ep.download_path.clone(),
+        }
+    }
+}
+
+// A search result: the podcast or episode record plus the matching text, with matches
+// between [ and ].
+#[derive(Serialize)]
+pub struct SearchRecord<T> {
+    #[serde(flatten)]
+    pub item: T,
+    pub snippet: String,
+}
+
+// What `add` added.
+#[derive(Serialize)]
+pub struct AddRecord {
+    #[serde(flatten)]
+    pub podcast: PodcastRecord,
+    pub episodes: usize,
+}
+
+// What `update` did to one podcast. `status` is "updated", "unchanged" or "failed"; the
+// counts are zero and `error` is set when it failed.
+#[derive(Serialize)]
+pub struct UpdateRecord {
+    pub id: i64,
+    pub title: String,
+    pub status: &'static str,
+    pub new_episodes: Vec<i64>,
+    pub edited: usize,
+    pub removed: usize,
+    pub error: Option<String>,
+}
+
+// The whole of an update, as `--format json` prints it. With ndjson only the podcasts are
+// printed, one per line.
+#[derive(Serialize)]
+pub struct UpdateSummaryRecord {
+    pub podcasts: Vec<UpdateRecord>,
+    pub downloaded: usize,
+    pub download_failed: usize,
+}
+
+impl From<&actions::Summary> for UpdateSummaryRecord {
+    fn from(summary: &actions::Summary) -> UpdateSummaryRecord {
+        let mut podcasts = Vec::new();
+        for (id, title, changes) in &summary.updated {
+            podcasts.push(UpdateRecord {
+                id: *id,
+                title: title.clone(),
+                status: "updated",
+                new_episodes: changes.new.clone(),
+                edited: changes.edited,
+                removed: changes.removed,
+                error: None,
+            });
+        }
+        for (id, title) in &summary.unchanged {
+            podcasts.push(UpdateRecord {
+                id: *id,
+                title: title.clone(),
+                status: "unchanged",
+                new_episodes: Vec::new(),
+                edited: 0,
+                removed: 0,
+                error: None,
+            });
+        }
+        for (id, title, error) in &summary.failed {
+            podcasts.push(UpdateRecord {
+                id: *id,
+                title: title.clone(),
+                status: "failed",
+                new_episodes: Vec::new(),
+                edited: 0,
+                removed: 0,
+                error: Some(error.to_string()),
+            });
+        }
+        UpdateSummaryRecord {
+            podcasts,
+            downloaded: summary.downloaded,
+            download_failed: summary.download_failed,
+        }
+    }
+}
+
+// Something that can be a row of `--format table`.
+pub trait Row {
+    fn header() -> Vec<&'static str>;
+    fn cells(&self) -> Vec<String>;
+}
+
+impl Row for PodcastRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["ID", "TITLE", "AUTHOR", "CATEGORIES", "TAGS"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.title.clone(),
+            self.author.clone().unwrap_or_default(),
+            self.categories.join(", "),
+            self.tags.join(", "),
+        ]
+    }
+}
+
+impl Row for EpisodeRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["ID", "TITLE", "DATE", "DURATION", "STATE"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let state = if self.played {
+            "played"
+        } else if self.position > 0 {
+            "started"
+        } else {
+            ""
+        };
+        vec![
+            self.id.to_string(),
+            self.title.clone(),
+            self.pub_date
+                .as_deref()
+                .map(|d| d.get(..10).unwrap_or(d))
+                .unwrap_or_default()
+                .to_string(),
+            self.duration.map(podcast::format_time).unwrap_or_default(),
+            state.to_string(),
+        ]
+    }
+}
+
+impl Row for AddRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["ID", "TITLE", "EPISODES"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.podcast.id.to_string(),
+            self.podcast.title.clone(),
+            self.episodes.to_string(),
+        ]
+    }
+}
+
+impl Row for UpdateRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["ID", "TITLE", "STATUS", "NEW", "EDITED", "REMOVED", "ERROR"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.title.clone(),
+            self.status.to_string(),
+            self.new_episodes.len().to_string(),
+            self.edited.to_string(),
+            self.removed.to_string(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl<T: Row> Row for SearchRecord<T> {
+    fn header() -> Vec<&'static str> {
+        let mut header = T::header();
+        header.push("MATCH");
+        header
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let mut cells = self.item.cells();
+        cells.push(
+            self.snippet
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        cells
+    }
+}
+
+// Longest a table cell gets before it's cut short.
+const MAX_CELL_WIDTH: usize = 50;
+
+// Prints a list of results as a JSON array, one JSON object per line or a table.
+pub fn print_list<T: Serialize + Row>(format: Format, records: &[T]) {
+    match format {
+        Format::Json => println!("{}", to_json(&records)),
+        Format::Ndjson => {
+            for record in records {
+                println!("{}", to_json_line(record));
+            }
+        }
+        Format::Table | Format::Plain => print_table(records),
+    }
+}
+
+// Prints a single result as JSON; the other formats have their own wording.
+pub fn print_record<T: Serialize>(format: Format, record: &T) {
+    match format {
+        Format::Ndjson => println!("{}", to_json_line(record)),
+        _ => println!("{}", to_json(record)),
+    }
+}
+
+// Reports a failed command in the chosen format and exits with the code for its kind.
+pub fn fail(format: Format, e: anyhow::Error) -> ! {
+    let classified = Error::classify(&e);
+    let message = match &classified {
+        Some(classified) => classified.to_string(),
+        None => e.to_string(),
+    };
+    if format.is_json() {
+        let kind = classified.as_ref().map_or("other", |c| c.kind());
+        eprintln!("{}", serde_json::json!({ "error": message, "kind": kind }));
+    } else {
+        eprintln!("{}", message);
+    }
+    std::process::exit(classified.map_or(1, |c| c.exit_code()));
+}
+
+fn print_table<T: Row>(records: &[T]) {
+    let header = T::header();
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|r| r.cells().into_iter().map(|c| truncate(&c)).collect())
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:w$}", c, w = w))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(header);
+    for row in &rows {
+        line(row.iter().map(|c| c.as_str()).collect());
+    }
+}
+
+fn truncate(cell: &str) -> String {
+    let cell = cell.replace(['\n', '\t'], " ");
+    if cell.chars().count() <= MAX_CELL_WIDTH {
+        return cell;
+    }
+    let mut cut: String = cell.chars().take(MAX_CELL_WIDTH - 1).collect();
+    cut.push('…');
+    cut
+}
+
+// Serializing our own records can't fail.
+fn to_json<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+fn to_json_line<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
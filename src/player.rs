@@ -0,0 +1,380 @@
+use crate::db;
+use crate::error::Error;
+use crate::podcast;
+use anyhow::Result;
+use libmpv::events::{Event, PropertyData};
+use libmpv::{FileState, Format, Mpv};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+
+// Stopping within this many seconds of the end counts as having heard the episode.
+const PLAYED_THRESHOLD: i64 = 30;
+
+// libmpv errors hold an Rc, so they can't cross into anyhow as-is.
+pub fn mpv_error(e: libmpv::Error) -> anyhow::Error {
+    Error::Player(format!("mpv: {:?}", e)).into()
+}
+
+// Plays an episode in the foreground using mpv's own terminal controls, resuming from the
+// stored position and saving progress once mpv quits or the file ends.
+pub fn play(conn: &rusqlite::Connection, ep: &podcast::Episode) -> Result<()> {
+    let url = ep
+        .media_url()
+        .ok_or_else(|| Error::NotFound(format!("{} has no enclosure to play.", ep.title)))?;
+    let start = ep.resume_position();
+    let speed = speed_for(conn, ep)?;
+    let mpv = Mpv::with_initializer(|init| {
+        init.set_property("terminal", true)?;
+        init.set_property("input-terminal", true)?;
+        init.set_property("input-default-bindings", true)?;
+        init.set_property("start", start.to_string())?;
+        init.set_property("speed", speed)?;
+        Ok(())
+    })
+    .map_err(mpv_error)?;
+    let mut events = mpv.create_event_context();
+    events.disable_deprecated_events().map_err(mpv_error)?;
+    events
+        .observe_property("time-pos", Format::Double, 0)
+        .map_err(mpv_error)?;
+    events
+        .observe_property("duration", Format::Double, 0)
+        .map_err(mpv_error)?;
+    mpv.playlist_load_files(&[(&url, FileState::AppendPlay, None)])
+        .map_err(mpv_error)?;
+
+    let mut position = start;
+    let mut duration = ep.duration;
+    loop {
+        match events.wait_event(1.0) {
+            Some(Ok(Event::PropertyChange {
+                name: "time-pos",
+                change: PropertyData::Double(t),
+                ..
+            })) => position = t as i64,
+            Some(Ok(Event::PropertyChange {
+                name: "duration",
+                change: PropertyData::Double(d),
+                ..
+            })) => duration = Some(d as i64),
+            Some(Ok(Event::EndFile(_))) | Some(Ok(Event::Shutdown)) => break,
+            Some(Err(e)) => return Err(mpv_error(e)),
+            _ => (),
+        }
+    }
+    save_progress(conn, ep.id, position, duration)?;
+    Ok(())
+}
+
+// Hands an episode to an external player, e.g. "mpv --no-video", and waits for it to quit.
+// A "{start}" in the command becomes the seconds to resume from; mpv is told where to start
+// without one. Progress isn't saved, as there's no knowing how far the player got.
+pub fn play_with(command: &str, ep: &podcast::Episode) -> Result<()> {
+    let url = ep
+        .media_url()
+        .ok_or_else(|| Error::NotFound(format!("{} has no enclosure to play.", ep.title)))?;
+    let start = ep.resume_position().to_string();
+    let mut args: Vec<String> = command
+        .split_whitespace()
+        .map(|word| word.replace("{start}", &start))
+        .collect();
+    if args.is_empty() {
+        return Err(Error::Player("The player command is empty.".to_string()).into());
+    }
+    let program = args.remove(0);
+    let is_mpv = Path::new(&program).file_stem().is_some_and(|s| s == "mpv");
+    if is_mpv && !command.contains("{start}") && ep.resume_position() > 0 {
+        args.push(format!("--start={}", start));
+    }
+    let status = std::process::Command::new(&program)
+        .args(&args)
+        .arg(&url)
+        .status()
+        .map_err(|e| Error::Player(format!("Can't run {}: {}", program, e)))?;
+    if !status.success() {
+        return Err(Error::Player(format!("{} quit with {}.", program, status)).into());
+    }
+    Ok(())
+}
+
+// The speed the episode's podcast is set to play at.
+fn speed_for(conn: &rusqlite::Connection, ep: &podcast::Episode) -> Result<f64> {
+    let pod = db::fetch_podcast_of_episode(conn, ep.id)?;
+    Ok(db::fetch_policy(conn, pod.id)?.speed.unwrap_or(1.0))
+}
+
+pub fn save_progress(
+    conn: &rusqlite::Connection,
+    id: i64,
+    position: i64,
+    duration: Option<i64>,
+) -> Result<()> {
+    db::save_position(conn, id, position, duration)?;
+    if is_finished(position, duration) {
+        db::set_played(conn, id, true)?;
+    }
+    Ok(())
+}
+
+fn is_finished(position: i64, duration: Option<i64>) -> bool {
+    match duration {
+        Some(d) => d > 0 && position >= d - PLAYED_THRESHOLD,
+        None => false,
+    }
+}
+
+pub enum Command {
+    Load(Box<podcast::Episode>),
+    TogglePause,
+    Seek(f64),
+    Volume(f64),
+    Speed(f64),
+    Stop,
+    Next,
+    Quit,
+}
+
+#[derive(Clone, Default)]
+pub struct Status {
+    pub episode: Option<podcast::Episode>,
+    pub paused: bool,
+    pub position: f64,
+    pub duration: Option<f64>,
+    pub volume: f64,
+    pub speed: f64,
+    pub error: Option<String>,
+    // Bumped whenever an episode starts, stops or finishes, so the UI knows to refresh.
+    pub changes: u64,
+}
+
+// How often progress is written back while an episode keeps playing.
+const SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+// An mpv instance running on its own thread. Commands go in over a channel and the latest
+// playback state can be read back at any time without blocking on mpv.
+pub struct Player {
+    tx: mpsc::Sender<Command>,
+    status: Arc<Mutex<Status>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Player {
+    pub fn spawn(db_name: String) -> Player {
+        let (tx, rx) = mpsc::channel();
+        let status = Arc::new(Mutex::new(Status {
+            volume: 100.0,
+            speed: 1.0,
+            ..Default::default()
+        }));
+        let thread_status = status.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(e) = run(db_name, rx, thread_status.clone()) {
+                let mut status = thread_status.lock().unwrap();
+                status.episode = None;
+                status.error = Some(e.to_string());
+            }
+        });
+        Player {
+            tx,
+            status,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, cmd: Command) {
+        // A closed channel means the player thread died, which is already reported in the
+        // status.
+        let _ = self.tx.send(cmd);
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.send(Command::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Worker<'a> {
+    mpv: &'a Mpv,
+    conn: rusqlite::Connection,
+    status: Arc<Mutex<Status>>,
+    // EndFile events still owed for files we replaced or stopped ourselves.
+    stale_ends: usize,
+    last_save: std::time::Instant,
+}
+
+fn run(db_name: String, rx: mpsc::Receiver<Command>, status: Arc<Mutex<Status>>) -> Result<()> {
+    let conn = db::init_db(&db_name)?;
+    let mpv = Mpv::with_initializer(|init| {
+        init.set_property("idle", true)?;
+        init.set_property("vid", "no")?;
+        Ok(())
+    })
+    .map_err(mpv_error)?;
+    let mut events = mpv.create_event_context();
+    events.disable_deprecated_events().map_err(mpv_error)?;
+    for (name, format) in [
+        ("time-pos", Format::Double),
+        ("duration", Format::Double),
+        ("pause", Format::Flag),
+        ("volume", Format::Double),
+        ("speed", Format::Double),
+    ] {
+        events
+            .observe_property(name, format, 0)
+            .map_err(mpv_error)?;
+    }
+    let mut worker = Worker {
+        mpv: &mpv,
+        conn,
+        status,
+        stale_ends: 0,
+        last_save: std::time::Instant::now(),
+    };
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(Command::Quit) | Err(mpsc::TryRecvError::Disconnected) => {
+                    return worker.save();
+                }
+                Ok(cmd) => worker.handle_command(cmd)?,
+                Err(mpsc::TryRecvError::Empty) => break,
+            }
+        }
+        match events.wait_event(0.1) {
+            Some(Ok(Event::PropertyChange { name, change, .. })) => {
+                worker.property_changed(name, change)
+            }
+            Some(Ok(Event::EndFile(_))) => worker.end_file()?,
+            Some(Ok(Event::Shutdown)) => return worker.save(),
+            Some(Err(e)) => return Err(mpv_error(e)),
+            _ => (),
+        }
+        if worker.last_save.elapsed() >= SAVE_INTERVAL {
+            worker.save()?;
+        }
+    }
+}
+
+impl<'a> Worker<'a> {
+    fn handle_command(&mut self, cmd: Command) -> Result<()> {
+        match cmd {
+            Command::Load(ep) => self.load(*ep)?,
+            Command::TogglePause => {
+                let paused = self.status.lock().unwrap().paused;
+                self.mpv.set_property("pause", !paused).map_err(mpv_error)?;
+            }
+            Command::Seek(secs) if secs >= 0.0 => self.mpv.seek_forward(secs).map_err(mpv_error)?,
+            Command::Seek(secs) => self.mpv.seek_backward(-secs).map_err(mpv_error)?,
+            Command::Volume(delta) => self
+                .mpv
+                .command("add", &["volume", &delta.to_string()])
+                .map_err(mpv_error)?,
+            Command::Speed(delta) => self
+                .mpv
+                .command("add", &["speed", &delta.to_string()])
+                .map_err(mpv_error)?,
+            Command::Stop => {
+                if self.status.lock().unwrap().episode.is_some() {
+                    self.save()?;
+                    self.stale_ends += 1;
+                    self.mpv.command("stop", &[]).map_err(mpv_error)?;
+                    self.finish();
+                }
+            }
+            Command::Next => match db::queue_pop(&self.conn)? {
+                Some(ep) => self.load(ep)?,
+                None => self.handle_command(Command::Stop)?,
+            },
+            Command::Quit => (),
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, ep: podcast::Episode) -> Result<()> {
+        let url = match ep.media_url() {
+            Some(url) => url,
+            None => {
+                self.status.lock().unwrap().error =
+                    Some(format!("{} has no enclosure to play.", ep.title));
+                return Ok(());
+            }
+        };
+        self.save()?;
+        // Whatever is playing now is no longer up next.
+        db::queue_remove(&self.conn, ep.id)?;
+        let start = ep.resume_position();
+        if self.status.lock().unwrap().episode.is_some() {
+            self.stale_ends += 1;
+        }
+        self.mpv
+            .set_property("start", format!("+{}", start))
+            .map_err(mpv_error)?;
+        self.mpv
+            .set_property("speed", speed_for(&self.conn, &ep)?)
+            .map_err(mpv_error)?;
+        self.mpv
+            .playlist_load_files(&[(&url, FileState::Replace, None)])
+            .map_err(mpv_error)?;
+        self.mpv.set_property("pause", false).map_err(mpv_error)?;
+        let mut status = self.status.lock().unwrap();
+        status.position = start as f64;
+        status.duration = ep.duration.map(|d| d as f64);
+        status.episode = Some(ep);
+        status.error = None;
+        status.changes += 1;
+        Ok(())
+    }
+
+    fn property_changed(&mut self, name: &str, change: PropertyData) {
+        let mut status = self.status.lock().unwrap();
+        match (name, change) {
+            ("time-pos", PropertyData::Double(t)) => status.position = t,
+            ("duration", PropertyData::Double(d)) => status.duration = Some(d),
+            ("pause", PropertyData::Flag(p)) => status.paused = p,
+            ("volume", PropertyData::Double(v)) => status.volume = v,
+            ("speed", PropertyData::Double(s)) => status.speed = s,
+            _ => (),
+        }
+    }
+
+    fn end_file(&mut self) -> Result<()> {
+        if self.stale_ends > 0 {
+            self.stale_ends -= 1;
+            return Ok(());
+        }
+        self.save()?;
+        self.finish();
+        match db::queue_pop(&self.conn)? {
+            Some(ep) => self.load(ep),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) {
+        let mut status = self.status.lock().unwrap();
+        status.episode = None;
+        status.position = 0.0;
+        status.duration = None;
+        status.changes += 1;
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.last_save = std::time::Instant::now();
+        let (id, position, duration) = {
+            let status = self.status.lock().unwrap();
+            match &status.episode {
+                Some(ep) => (ep.id, status.position, status.duration),
+                None => return Ok(()),
+            }
+        };
+        save_progress(&self.conn, id, position as i64, duration.map(|d| d as i64))
+    }
+}
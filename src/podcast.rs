@@ -2,7 +2,7 @@ use std::option::Option;
 
 const NO_ID: i64 = -1;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Podcast {
     pub title: String,
     pub description: String,
@@ -11,6 +11,17 @@ pub struct Podcast {
     pub language: Option<String>,
     pub pub_date: Option<String>,
     pub last_build_date: Option<String>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub summary: Option<String>,
+    pub explicit: Option<bool>,
+    pub categories: Vec<String>,
+    pub block: bool,
+    pub locked: bool,
+    // Our own labels, unlike the categories the feed gives. Nested ones look like
+    // "News/Tech", as they do when they come from OPML folders.
+    pub tags: Vec<String>,
+    pub funding: Vec<Funding>,
     pub episodes: Vec<Episode>,
     pub id: i64,
 }
@@ -25,42 +36,75 @@ impl Podcast {
             language: None,
             pub_date: None,
             last_build_date: None,
+            author: None,
+            image: None,
+            summary: None,
+            explicit: None,
+            categories: Vec::new(),
+            block: false,
+            locked: false,
+            tags: Vec::new(),
+            funding: Vec::new(),
             episodes: Vec::new(),
             id: NO_ID,
         }
     }
 
-    pub fn print(&self, detailed: bool) {
-        if detailed {
-            self.print_detailed();
-        } else {
-            self.print_summary();
-        }
-    }
-
     pub fn str(&self, detailed: bool) -> String {
         if !detailed {
-            format!("{} => {}", self.id, self.title)
+            format!(
+                "{} => {}{}",
+                self.id,
+                self.title,
+                explicit_tag(self.explicit)
+            )
         } else {
-            format!("{:?}", self)
+            self.to_string()
         }
     }
 
-    fn print_summary(&self) {
-        println!("{} => {}", self.id, self.title);
+    // Categories match case-insensitively, and a top-level category also matches its
+    // subcategories.
+    pub fn in_category(&self, category: &str) -> bool {
+        let category = category.to_lowercase();
+        self.categories.iter().any(|c| {
+            let c = c.to_lowercase();
+            c == category || c.starts_with(&format!("{}/", category))
+        })
     }
 
-    fn print_detailed(&self) {
-        println!("{:?}", self);
+    // The same goes for tags.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.to_lowercase();
+        self.tags.iter().any(|t| {
+            let t = t.to_lowercase();
+            t == tag || t.starts_with(&format!("{}/", tag))
+        })
     }
 }
 
 impl std::fmt::Display for Podcast {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!(
-            "{} => {}\nDescription: {}\n",
-            self.id, self.title, self.description
-        ))
+            "{} => {}{}\nDescription: {}\n",
+            self.id,
+            self.title,
+            explicit_tag(self.explicit),
+            self.description
+        ))?;
+        if let Some(author) = &self.author {
+            f.write_str(&format!("Author: {}\n", author))?;
+        }
+        if !self.categories.is_empty() {
+            f.write_str(&format!("Categories: {}\n", self.categories.join(", ")))?;
+        }
+        if !self.tags.is_empty() {
+            f.write_str(&format!("Tags: {}\n", self.tags.join(", ")))?;
+        }
+        for funding in &self.funding {
+            f.write_str(&format!("Support: {} ({})\n", funding.text, funding.url))?;
+        }
+        Ok(())
     }
 }
 
@@ -72,6 +116,31 @@ pub struct Episode {
     pub pub_date: Option<String>,
     pub link: Option<String>,
     pub enclosure: Option<Enclosure>,
+    pub played: bool,
+    pub position: i64,
+    pub duration: Option<i64>,
+    pub last_played: Option<String>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub summary: Option<String>,
+    pub explicit: Option<bool>,
+    pub episode_number: Option<i64>,
+    pub season: Option<i64>,
+    pub episode_type: Option<String>,
+    pub block: bool,
+    pub season_name: Option<String>,
+    pub episode_display: Option<String>,
+    pub chapters: Option<Chapters>,
+    pub transcripts: Vec<Transcript>,
+    pub persons: Vec<Person>,
+    pub soundbites: Vec<Soundbite>,
+    pub revisions: Vec<Revision>,
+    // The feed no longer lists this episode.
+    pub removed: bool,
+    // Kept safe from retention policies.
+    pub starred: bool,
+    // Where the episode was downloaded to, if it has been.
+    pub download_path: Option<String>,
     pub id: i64,
 }
 
@@ -84,47 +153,376 @@ impl Episode {
             pub_date: None,
             link: None,
             enclosure: None,
+            played: false,
+            position: 0,
+            duration: None,
+            last_played: None,
+            author: None,
+            image: None,
+            summary: None,
+            explicit: None,
+            episode_number: None,
+            season: None,
+            episode_type: None,
+            block: false,
+            season_name: None,
+            episode_display: None,
+            chapters: None,
+            transcripts: Vec::new(),
+            persons: Vec::new(),
+            soundbites: Vec::new(),
+            revisions: Vec::new(),
+            removed: false,
+            starred: false,
+            download_path: None,
             id: NO_ID,
         }
     }
 
-    pub fn print(&self, detailed: bool) {
-        if detailed {
-            self.print_detailed();
+    pub fn str(&self, detailed: bool) -> String {
+        if !detailed {
+            format!(
+                "{} => {}{}{}{}{}{}{}{}",
+                self.id,
+                self.numbering(),
+                self.title,
+                self.type_tag(),
+                explicit_tag(self.explicit),
+                self.starred_tag(),
+                self.removed_tag(),
+                self.offline_tag(),
+                self.progress()
+            )
         } else {
-            self.print_summary();
+            self.to_string()
         }
     }
 
-    pub fn str(&self, detailed: bool) -> String {
-        if !detailed {
-            format!("{} => {}", self.id, self.title)
+    // Where playback should pick up: played episodes start over.
+    pub fn resume_position(&self) -> i64 {
+        if self.played {
+            0
         } else {
-            format!("{:?}", self)
+            self.position
         }
     }
 
-    fn print_summary(&self) {
-        println!("{} => {}", self.id, self.title);
+    pub fn marker(&self) -> &'static str {
+        if self.played {
+            "✔ "
+        } else if self.position > 0 {
+            "▸ "
+        } else {
+            "  "
+        }
     }
 
-    fn print_detailed(&self) {
-        println!("{:?}", self);
+    // "S2E5 ", "E5 " or nothing, depending on what the feed tells us.
+    pub fn numbering(&self) -> String {
+        match (self.season, self.episode_number) {
+            (Some(s), Some(e)) => format!("S{}E{} ", s, e),
+            (None, Some(e)) => format!("E{} ", e),
+            _ => String::new(),
+        }
+    }
+
+    // Full episodes are the norm, so only trailers and bonus material get called out.
+    fn type_tag(&self) -> String {
+        match self.episode_type.as_deref() {
+            None | Some("full") => String::new(),
+            Some(t) => format!(" [{}]", t),
+        }
+    }
+
+    // Where to play the episode from: the downloaded file while it's still there, otherwise
+    // the enclosure.
+    pub fn media_url(&self) -> Option<String> {
+        match &self.download_path {
+            Some(path) if std::path::Path::new(path).exists() => Some(path.clone()),
+            _ => self.enclosure.as_ref().map(|e| e.url.clone()),
+        }
+    }
+
+    pub fn offline_tag(&self) -> &'static str {
+        if self.download_path.is_some() {
+            " [offline]"
+        } else {
+            ""
+        }
+    }
+
+    pub fn starred_tag(&self) -> &'static str {
+        if self.starred {
+            " ★"
+        } else {
+            ""
+        }
+    }
+
+    fn removed_tag(&self) -> &'static str {
+        if self.removed {
+            " [removed]"
+        } else {
+            ""
+        }
+    }
+
+    fn progress(&self) -> String {
+        if self.played {
+            " (played)".to_string()
+        } else if self.position > 0 {
+            match self.duration {
+                Some(d) => format!(" ({}/{})", format_time(self.position), format_time(d)),
+                None => format!(" ({})", format_time(self.position)),
+            }
+        } else {
+            String::new()
+        }
     }
 }
 
 impl std::fmt::Display for Episode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!(
-            "{} => {}\nDescription: {}\n",
-            self.id, self.title, self.description
-        ))
+            "{} => {}{}{}{}{}{}{}{}\nDescription: {}\n",
+            self.id,
+            self.numbering(),
+            self.title,
+            self.type_tag(),
+            explicit_tag(self.explicit),
+            self.starred_tag(),
+            self.removed_tag(),
+            self.offline_tag(),
+            self.progress(),
+            self.description
+        ))?;
+        if let Some(author) = &self.author {
+            f.write_str(&format!("Author: {}\n", author))?;
+        }
+        for person in &self.persons {
+            f.write_str(&format!(
+                "{}: {}\n",
+                person.role.as_deref().unwrap_or("host"),
+                person.name
+            ))?;
+        }
+        if let Some(chapters) = &self.chapters {
+            f.write_str(&format!("Chapters: {}\n", chapters.url))?;
+        }
+        for transcript in &self.transcripts {
+            f.write_str(&format!(
+                "Transcript: {} ({})\n",
+                transcript.url, transcript.mime_type
+            ))?;
+        }
+        for soundbite in &self.soundbites {
+            f.write_str(&format!(
+                "Soundbite: {} at {}\n",
+                soundbite.title.as_deref().unwrap_or("untitled"),
+                format_time(soundbite.start as i64)
+            ))?;
+        }
+        for revision in &self.revisions {
+            f.write_str(&format!(
+                "Audio replaced {}, was {} ({} bytes)\n",
+                revision.changed_at,
+                revision.enclosure_url.as_deref().unwrap_or("no audio"),
+                revision.enclosure_length.as_deref().unwrap_or("?")
+            ))?;
+        }
+        if let Some(path) = &self.download_path {
+            f.write_str(&format!("Downloaded to: {}\n", path))?;
+        }
+        if let Some(last_played) = &self.last_played {
+            f.write_str(&format!("Last played: {}\n", last_played))?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Enclosure {
     pub url: String,
     pub length: Option<String>,
     pub mime_type: Option<String>,
 }
+
+// What we last saw of a podcast's feed, so an update can ask the server whether anything
+// changed and skip the parse when it didn't.
+#[derive(Debug, Clone, Default)]
+pub struct FeedCache {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Funding {
+    pub url: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapters {
+    pub url: String,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub url: String,
+    pub mime_type: String,
+    pub language: Option<String>,
+    pub rel: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Person {
+    pub name: String,
+    pub role: Option<String>,
+    pub group: Option<String>,
+    pub img: Option<String>,
+    pub href: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Soundbite {
+    pub start: f64,
+    pub duration: f64,
+    pub title: Option<String>,
+}
+
+// The enclosure an episode had before the publisher replaced it.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub changed_at: String,
+    pub enclosure_url: Option<String>,
+    pub enclosure_length: Option<String>,
+}
+
+// How a podcast's episodes are downloaded, played and cleaned up. Starred episodes are
+// never deleted, whatever the policy says.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    // Download new episodes as `update` finds them.
+    pub auto_download: bool,
+    // Only keep this many of the newest downloaded episodes.
+    pub keep_last: Option<i64>,
+    // Delete downloads older than this.
+    pub max_age_days: Option<i64>,
+    pub delete_played: bool,
+    // Play at this speed rather than normal speed.
+    pub speed: Option<f64>,
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!(
+            "Auto-download: {}\n",
+            if self.auto_download { "on" } else { "off" }
+        ))?;
+        if let Some(n) = self.keep_last {
+            f.write_str(&format!("Keep the last {} episodes\n", n))?;
+        }
+        if let Some(days) = self.max_age_days {
+            f.write_str(&format!("Delete downloads after {} days\n", days))?;
+        }
+        if self.delete_played {
+            f.write_str("Delete played episodes\n")?;
+        }
+        if let Some(speed) = self.speed {
+            f.write_str(&format!("Play at {}x\n", speed))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DownloadedFile {
+    pub episode: Episode,
+    pub podcast_id: i64,
+    pub size: u64,
+    pub downloaded_at: String,
+}
+
+// A search result along with the bit of its text that matched.
+pub struct SearchHit<T> {
+    pub item: T,
+    pub snippet: String,
+}
+
+#[derive(Default, Clone)]
+pub struct EpisodeFilter {
+    pub season: Option<i64>,
+    pub episode_type: Option<String>,
+    pub hide_explicit: bool,
+}
+
+impl EpisodeFilter {
+    pub fn matches(&self, ep: &Episode) -> bool {
+        if self.season.is_some() && ep.season != self.season {
+            return false;
+        }
+        if let Some(t) = &self.episode_type {
+            // Feeds that don't say are publishing full episodes.
+            if !t.eq_ignore_ascii_case(ep.episode_type.as_deref().unwrap_or("full")) {
+                return false;
+            }
+        }
+        !(self.hide_explicit && ep.explicit == Some(true))
+    }
+}
+
+// Tidies a tag as typed or as an OPML folder path: the stray spaces and empty levels in
+// " News / /Tech" go, leaving "News/Tech". None if nothing is left.
+pub fn clean_tag(tag: &str) -> Option<String> {
+    let levels: Vec<&str> = tag
+        .split('/')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    Some(levels.join("/")).filter(|t| !t.is_empty())
+}
+
+// Descriptions are usually HTML, which is no use where only text will do, like a file's
+// tags or a title.
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn explicit_tag(explicit: Option<bool>) -> &'static str {
+    if explicit == Some(true) {
+        " [explicit]"
+    } else {
+        ""
+    }
+}
+
+pub fn format_time(secs: i64) -> String {
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{:02}:{:02}", m, s)
+    }
+}
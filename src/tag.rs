@@ -0,0 +1,183 @@
+use crate::db;
+use crate::feed;
+use crate::podcast;
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Timelike};
+use id3::TagLike;
+use std::path::Path;
+
+const ARTWORK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// What tagging a downloaded file managed.
+pub struct Tagged {
+    // Why the artwork was left out, when it was. The rest of the tags are written either way.
+    pub artwork_error: Option<anyhow::Error>,
+}
+
+enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+struct Artwork {
+    format: ImageFormat,
+    data: Vec<u8>,
+}
+
+// Writes the podcast's and episode's details into a downloaded file so players that go by
+// tags show something better than "Unknown Artist": ID3v2 for MP3s, iTunes-style atoms for
+// MP4s. The download's recorded size is brought up to date afterwards, since tags and
+// artwork take up room.
+pub fn tag_download(
+    conn: &rusqlite::Connection,
+    path: &Path,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+) -> Result<Tagged> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mp4 = match ext.as_str() {
+        "mp3" => false,
+        "m4a" | "m4b" | "mp4" | "m4v" | "mov" => true,
+        _ => return Err(anyhow!("Tagging .{} files isn't supported.", ext)),
+    };
+    let artwork = match ep.image.as_deref().or(pod.image.as_deref()) {
+        Some(url) => fetch_artwork(url),
+        None => Err(anyhow!("the feed has no artwork")),
+    };
+    let (artwork, artwork_error) = match artwork {
+        Ok(artwork) => (Some(artwork), None),
+        Err(e) => (None, Some(e)),
+    };
+    if mp4 {
+        write_mp4(path, pod, ep, artwork.as_ref())?;
+    } else {
+        write_id3(path, pod, ep, artwork.as_ref())?;
+    }
+    let size = std::fs::metadata(path)?.len();
+    db::record_download(conn, ep.id, &path.to_string_lossy(), size)?;
+    Ok(Tagged { artwork_error })
+}
+
+fn write_id3(
+    path: &Path,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+    artwork: Option<&Artwork>,
+) -> Result<()> {
+    // Whatever the publisher put there stays, unless we have something better.
+    let mut tag = id3::no_tag_ok(id3::Tag::read_from_path(path))?.unwrap_or_default();
+    tag.set_title(&ep.title);
+    tag.set_album(&pod.title);
+    if let Some(author) = ep.author.as_ref().or(pod.author.as_ref()) {
+        tag.set_artist(author);
+    }
+    if let Some(author) = &pod.author {
+        tag.set_album_artist(author);
+    }
+    tag.set_genre("Podcast");
+    if let Some(date) = ep.pub_date.as_deref().and_then(feed::parse_date) {
+        let timestamp = id3::Timestamp {
+            year: date.year(),
+            month: Some(date.month() as u8),
+            day: Some(date.day() as u8),
+            hour: Some(date.hour() as u8),
+            minute: Some(date.minute() as u8),
+            second: Some(date.second() as u8),
+        };
+        tag.set_date_recorded(timestamp);
+        tag.set_date_released(timestamp);
+    }
+    if let Some(n) = ep.episode_number.filter(|n| *n > 0) {
+        tag.set_track(n as u32);
+    }
+    let description = podcast::plain_text(&ep.description);
+    if !description.is_empty() {
+        tag.remove("COMM");
+        tag.add_frame(id3::frame::Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: description,
+        });
+    }
+    // The podcast frames iTunes reads: the feed, and the episode's guid.
+    tag.add_frame(id3::Frame::link("WFED", &pod.rss_url));
+    tag.add_frame(id3::Frame::text("TGID", &ep.guid));
+    if let Some(artwork) = artwork {
+        tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+        tag.add_frame(id3::frame::Picture {
+            mime_type: match artwork.format {
+                ImageFormat::Jpeg => "image/jpeg",
+                ImageFormat::Png => "image/png",
+            }
+            .to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: artwork.data.clone(),
+        });
+    }
+    tag.write_to_path(path, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+fn write_mp4(
+    path: &Path,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+    artwork: Option<&Artwork>,
+) -> Result<()> {
+    let mut tag = mp4ameta::Tag::read_from_path(path)?;
+    tag.set_title(&ep.title);
+    tag.set_album(&pod.title);
+    if let Some(author) = ep.author.as_ref().or(pod.author.as_ref()) {
+        tag.set_artist(author);
+    }
+    if let Some(author) = &pod.author {
+        tag.set_album_artist(author);
+    }
+    tag.set_genre("Podcast");
+    if let Some(date) = ep.pub_date.as_deref().and_then(feed::parse_date) {
+        tag.set_year(date.format("%Y-%m-%d").to_string());
+    }
+    if let Some(n) = ep.episode_number.and_then(|n| u16::try_from(n).ok()) {
+        tag.set_track_number(n);
+    }
+    let description = podcast::plain_text(&ep.description);
+    if !description.is_empty() {
+        tag.set_description(description.clone());
+        tag.set_comment(description);
+    }
+    if let Some(artwork) = artwork {
+        let data = artwork.data.clone();
+        tag.set_artwork(match artwork.format {
+            ImageFormat::Jpeg => mp4ameta::Img::jpeg(data),
+            ImageFormat::Png => mp4ameta::Img::png(data),
+        });
+    }
+    tag.write_to_path(path)?;
+    Ok(())
+}
+
+fn fetch_artwork(url: &str) -> Result<Artwork> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(ARTWORK_TIMEOUT)
+        .build()?;
+    let data = client
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .bytes()?
+        .to_vec();
+    // Go by the bytes, as servers are vague about image types. Both tag formats can carry
+    // JPEG and PNG, and that's all podcast artwork is meant to be.
+    let format = if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        ImageFormat::Jpeg
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        ImageFormat::Png
+    } else {
+        return Err(anyhow!("the artwork at {} is neither JPEG nor PNG", url));
+    };
+    Ok(Artwork { format, data })
+}
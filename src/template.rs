@@ -0,0 +1,231 @@
+use crate::feed;
+use crate::podcast;
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use std::path::PathBuf;
+
+pub const DEFAULT_TEMPLATE: &str = "{podcast}/{date:%Y-%m-%d} {title}.{ext}";
+
+// Longest a single substituted value may get, leaving room for the rest of the name.
+const MAX_VALUE_LEN: usize = 120;
+
+// A filename template such as `{podcast}/{date:%Y}/{episode_number:03} - {title}.{ext}`.
+// Fields are podcast, title, date, episode_number, season, id, guid and ext. `date` takes a
+// strftime format and the numbers a width, zero-padded when it starts with 0. `/` separates
+// directories, and `{{`/`}}` are literal braces.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Literal(String),
+    Field(Field, Option<String>),
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Podcast,
+    Title,
+    Date,
+    EpisodeNumber,
+    Season,
+    Id,
+    Guid,
+    Ext,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template> {
+        if template.starts_with('/') {
+            return Err(anyhow!(
+                "Filename templates are relative to the download directory."
+            ));
+        }
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(anyhow!("Unclosed {{ in filename template.")),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_field(&spec)?);
+                }
+                '}' => return Err(anyhow!("Unmatched }} in filename template.")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    // The episode's path relative to the download directory. Every substituted value is
+    // sanitized, so only the template's own slashes create directories.
+    pub fn render(&self, pod: &podcast::Podcast, ep: &podcast::Episode) -> PathBuf {
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => path.push_str(s),
+                Part::Field(field, spec) => {
+                    path.push_str(&sanitize(&field_value(*field, spec.as_deref(), pod, ep)))
+                }
+            }
+        }
+        path.split('/')
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .collect()
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Part> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format.to_string())),
+        None => (spec, None),
+    };
+    let field = match name.trim() {
+        "podcast" => Field::Podcast,
+        "title" => Field::Title,
+        "date" => Field::Date,
+        "episode_number" => Field::EpisodeNumber,
+        "season" => Field::Season,
+        "id" => Field::Id,
+        "guid" => Field::Guid,
+        "ext" => Field::Ext,
+        other => return Err(anyhow!("Unknown field {{{}}} in filename template.", other)),
+    };
+    match (field, &format) {
+        (Field::Date, Some(f)) if StrftimeItems::new(f).any(|i| i == Item::Error) => {
+            Err(anyhow!("Bad date format {:?} in filename template.", f))
+        }
+        (Field::EpisodeNumber | Field::Season | Field::Id, Some(f))
+            if f.parse::<usize>().is_err() =>
+        {
+            Err(anyhow!("Bad number width {:?} in filename template.", f))
+        }
+        _ => Ok(Part::Field(field, format)),
+    }
+}
+
+fn field_value(
+    field: Field,
+    spec: Option<&str>,
+    pod: &podcast::Podcast,
+    ep: &podcast::Episode,
+) -> String {
+    match field {
+        Field::Podcast => pod.title.clone(),
+        Field::Title => ep.title.clone(),
+        Field::Date => match ep.pub_date.as_deref().and_then(feed::parse_date) {
+            Some(date) => date.format(spec.unwrap_or("%Y-%m-%d")).to_string(),
+            None => "undated".to_string(),
+        },
+        Field::EpisodeNumber => pad(ep.episode_number, spec),
+        Field::Season => pad(ep.season, spec),
+        Field::Id => pad(Some(ep.id), spec),
+        Field::Guid => ep.guid.clone(),
+        Field::Ext => extension(ep),
+    }
+}
+
+fn pad(n: Option<i64>, spec: Option<&str>) -> String {
+    let n = match n {
+        Some(n) => n,
+        None => return String::new(),
+    };
+    match spec {
+        Some(w) if w.starts_with('0') => format!("{:0width$}", n, width = w.parse().unwrap_or(0)),
+        Some(w) => format!("{:width$}", n, width = w.parse().unwrap_or(0)),
+        None => n.to_string(),
+    }
+}
+
+// Picks a file extension from the enclosure's MIME type, falling back to the one in its URL.
+pub fn extension(ep: &podcast::Episode) -> String {
+    let enclosure = match &ep.enclosure {
+        Some(enclosure) => enclosure,
+        None => return "mp3".to_string(),
+    };
+    let from_mime = match enclosure
+        .mime_type
+        .as_deref()
+        .map(|m| {
+            m.split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase()
+        })
+        .as_deref()
+    {
+        Some("audio/mpeg" | "audio/mp3" | "audio/mpeg3") => Some("mp3"),
+        Some("audio/mp4" | "audio/x-m4a" | "audio/m4a" | "audio/aac-mp4") => Some("m4a"),
+        Some("audio/aac" | "audio/aacp") => Some("aac"),
+        Some("audio/ogg" | "application/ogg" | "audio/vorbis") => Some("ogg"),
+        Some("audio/opus") => Some("opus"),
+        Some("audio/flac" | "audio/x-flac") => Some("flac"),
+        Some("audio/wav" | "audio/x-wav" | "audio/wave") => Some("wav"),
+        Some("video/mp4") => Some("mp4"),
+        Some("video/x-m4v") => Some("m4v"),
+        Some("video/quicktime") => Some("mov"),
+        Some("video/webm" | "audio/webm") => Some("webm"),
+        _ => None,
+    };
+    if let Some(ext) = from_mime {
+        return ext.to_string();
+    }
+    reqwest::Url::parse(&enclosure.url)
+        .ok()
+        .and_then(|url| {
+            let name = url.path_segments()?.next_back()?.to_string();
+            let (_, ext) = name.rsplit_once('.')?;
+            (!ext.is_empty() && ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+                .then(|| ext.to_lowercase())
+        })
+        .unwrap_or_else(|| "mp3".to_string())
+}
+
+// Makes a value safe to use as (part of) a file name on any common filesystem.
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let mut cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.len() > MAX_VALUE_LEN {
+        let mut end = MAX_VALUE_LEN;
+        while !cleaned.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned.truncate(end);
+    }
+    let cleaned = cleaned.trim_end_matches(['.', ' ']);
+    // No hidden files, and no `..` sneaking out of the download directory.
+    match cleaned.strip_prefix('.') {
+        Some(rest) => format!("_{}", rest),
+        None => cleaned.to_string(),
+    }
+}
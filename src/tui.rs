@@ -1,27 +1,81 @@
 mod episodes_page;
+mod keys;
+mod player_page;
 mod pods_page;
+mod queue_page;
 
+use crate::actions::{self, Action, ActionEvent};
 use crate::tui::episodes_page::EpisodesPage;
+use crate::tui::keys::{KeyAction, Keymap};
+use crate::tui::player_page::PlayerPage;
 use crate::tui::pods_page::PodcastsPage;
-use crate::{db, podcast};
+use crate::tui::queue_page::QueuePage;
+use crate::{config, db, podcast};
+use anyhow::{anyhow, Result};
 use crossterm::{event, execute, terminal};
 use ratatui::{prelude::*, widgets};
 use std::io;
+use std::sync::mpsc;
 
 trait Page {
     fn render(&mut self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect);
 }
 
+// The TUI's colours, from the config file's `[colours]` table.
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub accent: Color,
+    pub tabs: Color,
+    pub background: Color,
+    pub border: Color,
+}
+
+impl Theme {
+    fn new(colours: &config::Colours) -> Result<Theme> {
+        let colour = |name: &str, value: &Option<String>, default: Color| match value {
+            Some(value) => value
+                .parse()
+                .map_err(|_| anyhow!("Don't know the colour {:?} for {}.", value, name)),
+            None => Ok(default),
+        };
+        Ok(Theme {
+            accent: colour("accent", &colours.accent, Color::Yellow)?,
+            tabs: colour("tabs", &colours.tabs, Color::Cyan)?,
+            background: colour("background", &colours.background, Color::DarkGray)?,
+            border: colour("border", &colours.border, Color::Blue)?,
+        })
+    }
+}
+
 struct App {
+    db_name: String,
+    keymap: Keymap,
+    theme: Theme,
+    // What the background update reports as it goes.
+    updates: Option<mpsc::Receiver<ActionEvent>>,
+    // The latest word from the background update, shown next to the tabs.
+    status: Option<String>,
     #[allow(dead_code)]
     podcasts: std::rc::Rc<Vec<podcast::Podcast>>,
     layout: Layout,
     selected_tab: usize,
     podcast_page: PodcastsPage,
     episodes_page: EpisodesPage,
+    queue_page: QueuePage,
+    player_page: PlayerPage,
 }
 
-pub fn start() -> Result<(), io::Error> {
+// Runs the TUI on the given database. With an update interval in the config, `update` runs
+// in the background that often and the lists refresh when it's done.
+pub fn start(db_name: String, config: &config::Config, update: actions::Update) -> Result<()> {
+    // Bad settings are reported before the screen is taken over.
+    let keymap = Keymap::new(&config.keys)?;
+    let theme = Theme::new(&config.colours)?;
+    let updates = config
+        .update_interval
+        .filter(|minutes| *minutes > 0)
+        .map(|minutes| spawn_updates(db_name.clone(), update, minutes));
+
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, terminal::EnterAlternateScreen)?;
@@ -33,14 +87,14 @@ pub fn start() -> Result<(), io::Error> {
             widgets::Block::default()
                 .title("dipper")
                 .borders(widgets::Borders::ALL)
-                .border_style(Style::default().fg(Color::Yellow))
+                .border_style(Style::default().fg(theme.accent))
                 .border_type(widgets::BorderType::Thick)
-                .title_style(Style::default().fg(Color::Yellow)),
+                .title_style(Style::default().fg(theme.accent)),
         );
         f.render_widget(loading, size);
     })?;
 
-    let mut app = App::new("test.db".to_string());
+    let mut app = App::new(db_name, keymap, theme, updates);
     app.run(&mut terminal);
 
     terminal::disable_raw_mode()?;
@@ -50,20 +104,122 @@ pub fn start() -> Result<(), io::Error> {
     Ok(())
 }
 
+// Runs `update` every `minutes` on its own thread and connection.
+fn spawn_updates(
+    db_name: String,
+    update: actions::Update,
+    minutes: u64,
+) -> mpsc::Receiver<ActionEvent> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(minutes * 60));
+        let result = db::init_db(&db_name)
+            .map_err(anyhow::Error::from)
+            .and_then(|conn| update.execute(tx.clone(), conn));
+        // Failing to send means the TUI has gone, so there's no one left to update for.
+        if let Err(e) = result {
+            let error = format!("Update failed: {}", e);
+            if tx.send(ActionEvent::Failed { error }).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
 impl App {
-    fn new(db_name: String) -> App {
-        let pods = std::rc::Rc::new(App::load_podcasts(db_name));
+    fn new(
+        db_name: String,
+        keymap: Keymap,
+        theme: Theme,
+        updates: Option<mpsc::Receiver<ActionEvent>>,
+    ) -> App {
+        let pods = std::rc::Rc::new(App::load_podcasts(db_name.clone()));
         App {
             podcasts: pods.clone(),
-            podcast_page: PodcastsPage::new(pods.clone()),
-            episodes_page: EpisodesPage::new(pods.clone()),
+            podcast_page: PodcastsPage::new(pods.clone(), theme),
+            episodes_page: EpisodesPage::new(pods.clone(), theme),
+            queue_page: QueuePage::new(App::load_queue(db_name.clone()), theme),
+            player_page: PlayerPage::new(db_name.clone(), theme),
+            db_name,
+            keymap,
+            theme,
+            updates,
+            status: None,
             layout: Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([Constraint::Min(2), Constraint::Min(0)].as_ref()),
+                .constraints(
+                    [
+                        Constraint::Min(2),
+                        Constraint::Min(0),
+                        Constraint::Length(3),
+                    ]
+                    .as_ref(),
+                ),
             selected_tab: 0,
         }
     }
 
+    fn reload(&mut self) {
+        let pods = std::rc::Rc::new(App::load_podcasts(self.db_name.clone()));
+        self.podcasts = pods.clone();
+        self.podcast_page.set_podcasts(pods.clone());
+        self.episodes_page.set_podcasts(pods);
+        self.queue_page
+            .set_queue(App::load_queue(self.db_name.clone()));
+    }
+
+    fn selected_episode(&self) -> Option<&podcast::Episode> {
+        match self.selected_tab {
+            0 => self.podcast_page.selected_episode(),
+            1 => self.episodes_page.selected_episode(),
+            2 => self.queue_page.selected_episode(),
+            _ => None,
+        }
+    }
+
+    fn enqueue_selected(&mut self) {
+        if let Some(ep) = self.selected_episode() {
+            let id = ep.id;
+            let conn = db::init_db(&self.db_name).unwrap();
+            db::queue_add(&conn, id).unwrap();
+            self.reload();
+        }
+    }
+
+    fn dequeue_selected(&mut self) {
+        if let Some(ep) = self.queue_page.selected_episode() {
+            let id = ep.id;
+            let conn = db::init_db(&self.db_name).unwrap();
+            db::queue_remove(&conn, id).unwrap();
+            self.reload();
+        }
+    }
+
+    // Shifts the selected queue entry up (negative) or down (positive) and keeps it selected.
+    fn move_selected(&mut self, offset: isize) {
+        if let (Some(ep), Some(i)) = (
+            self.queue_page.selected_episode(),
+            self.queue_page.selected_index(),
+        ) {
+            let id = ep.id;
+            let target = i.saturating_add_signed(offset);
+            let conn = db::init_db(&self.db_name).unwrap();
+            db::queue_move(&conn, id, target + 1).unwrap();
+            self.reload();
+            self.queue_page.select(target);
+        }
+    }
+
+    fn toggle_played(&mut self) {
+        if let Some(ep) = self.selected_episode() {
+            let (id, played) = (ep.id, !ep.played);
+            let conn = db::init_db(&self.db_name).unwrap();
+            db::set_played(&conn, id, played).unwrap();
+            self.reload();
+        }
+    }
+
     fn load_podcasts(db_name: String) -> Vec<podcast::Podcast> {
         let conn = db::init_db(&db_name).unwrap();
         let mut pods = db::fetch_all_podcasts_and_episodes(&conn).unwrap();
@@ -71,8 +227,40 @@ impl App {
         pods
     }
 
+    fn load_queue(db_name: String) -> Vec<podcast::Episode> {
+        let conn = db::init_db(&db_name).unwrap();
+        db::fetch_queue(&conn).unwrap()
+    }
+
+    // Takes in what the background update has reported since last time, and says whether
+    // it has finished and the lists need reloading.
+    fn check_updates(&mut self) -> bool {
+        let mut finished = false;
+        let events = match &self.updates {
+            Some(updates) => updates.try_iter().collect::<Vec<_>>(),
+            None => return false,
+        };
+        for event in events {
+            match event {
+                ActionEvent::Started(what) => self.status = Some(what),
+                ActionEvent::Failed { error } => self.status = Some(error),
+                ActionEvent::Finished(summary) => {
+                    // The first line of the summary has the totals.
+                    self.status = summary.lines().next().map(str::to_string);
+                    finished = true;
+                }
+                _ => (),
+            }
+        }
+        finished
+    }
+
     fn run(&mut self, term: &mut Terminal<CrosstermBackend<io::Stdout>>) {
         loop {
+            let updated = self.check_updates();
+            if self.player_page.refresh() || updated {
+                self.reload();
+            }
             term.draw(|f| self.render(f)).unwrap();
             if !self.handle_input() {
                 break;
@@ -91,42 +279,86 @@ impl App {
             1 => {
                 self.episodes_page.render(f, rects[1]);
             }
+            2 => {
+                self.queue_page.render(f, rects[1]);
+            }
             _ => (),
         }
+        self.player_page.render(f, rects[2]);
     }
 
     fn handle_input(&mut self) -> bool {
+        // Wake up regularly even without input so the player's progress keeps moving.
+        if !event::poll(std::time::Duration::from_millis(250)).unwrap() {
+            return true;
+        }
         if let event::Event::Key(key) = event::read().unwrap() {
-            match key.code {
-                event::KeyCode::Char('q') => return false,
-                event::KeyCode::Char('j') => match self.selected_tab {
+            let action = match self.keymap.action(key.code) {
+                Some(action) => action,
+                None => return true,
+            };
+            match action {
+                KeyAction::Quit => return false,
+                KeyAction::Down => match self.selected_tab {
                     0 => self.podcast_page.select_next(),
                     1 => self.episodes_page.select_next(),
+                    2 => self.queue_page.select_next(),
                     _ => (),
                 },
-                event::KeyCode::Char('k') => match self.selected_tab {
+                KeyAction::Up => match self.selected_tab {
                     0 => self.podcast_page.select_previous(),
                     1 => self.episodes_page.select_previous(),
+                    2 => self.queue_page.select_previous(),
                     _ => (),
                 },
-                event::KeyCode::Char('u') => match self.selected_tab {
+                KeyAction::PageUp => match self.selected_tab {
                     0 => self.podcast_page.page_up(),
                     1 => self.episodes_page.page_up(),
+                    2 => self.queue_page.page_up(),
                     _ => (),
                 },
-                event::KeyCode::Char('d') => match self.selected_tab {
+                KeyAction::PageDown => match self.selected_tab {
                     0 => self.podcast_page.page_down(),
                     1 => self.episodes_page.page_down(),
+                    2 => self.queue_page.page_down(),
                     _ => (),
                 },
-                event::KeyCode::Char('h') => {
+                KeyAction::FocusPodcasts => {
                     self.podcast_page.focus_pod_list();
                 }
-                event::KeyCode::Char('l') => {
+                KeyAction::FocusEpisodes => {
                     self.podcast_page.focus_ep_list();
                 }
-                event::KeyCode::Tab => {
-                    self.selected_tab = (self.selected_tab + 1) % 2;
+                KeyAction::Play => {
+                    if let Some(ep) = self.selected_episode() {
+                        self.player_page.play(ep.clone());
+                    }
+                }
+                KeyAction::Pause => self.player_page.toggle_pause(),
+                KeyAction::Stop => self.player_page.stop(),
+                KeyAction::SeekBackward => self.player_page.seek_backward(),
+                KeyAction::SeekForward => self.player_page.seek_forward(),
+                KeyAction::VolumeDown => self.player_page.volume_down(),
+                KeyAction::VolumeUp => self.player_page.volume_up(),
+                KeyAction::SpeedDown => self.player_page.speed_down(),
+                KeyAction::SpeedUp => self.player_page.speed_up(),
+                KeyAction::Next => self.player_page.next(),
+                KeyAction::TogglePlayed => self.toggle_played(),
+                KeyAction::Enqueue => self.enqueue_selected(),
+                KeyAction::CycleTypeFilter if self.selected_tab == 1 => {
+                    self.episodes_page.cycle_type_filter()
+                }
+                KeyAction::ToggleExplicitFilter if self.selected_tab == 1 => {
+                    self.episodes_page.toggle_explicit_filter()
+                }
+                KeyAction::CycleTagFilter if self.selected_tab == 0 => {
+                    self.podcast_page.cycle_tag_filter()
+                }
+                KeyAction::Dequeue if self.selected_tab == 2 => self.dequeue_selected(),
+                KeyAction::MoveDown if self.selected_tab == 2 => self.move_selected(1),
+                KeyAction::MoveUp if self.selected_tab == 2 => self.move_selected(-1),
+                KeyAction::NextTab => {
+                    self.selected_tab = (self.selected_tab + 1) % 3;
                 }
                 _ => (),
             }
@@ -135,17 +367,24 @@ impl App {
     }
 
     fn render_tab_widget(&self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect) {
-        let tabs = widgets::Tabs::new(vec!["Podcasts", "Episodes"])
+        let tabs = widgets::Tabs::new(vec!["Podcasts", "Episodes", "Queue"])
             .block(
                 widgets::Block::default()
                     .borders(widgets::Borders::TOP)
-                    .blue()
-                    .title("dipper")
-                    .title_style(Style::default().fg(Color::Yellow)),
+                    .border_style(Style::default().fg(self.theme.border))
+                    .title(match &self.status {
+                        Some(status) => format!("dipper: {}", status),
+                        None => "dipper".to_string(),
+                    })
+                    .title_style(Style::default().fg(self.theme.accent)),
             )
             .divider("|")
-            .style(Style::default().fg(Color::Cyan))
-            .highlight_style(Style::default().fg(Color::Yellow).bg(Color::DarkGray))
+            .style(Style::default().fg(self.theme.tabs))
+            .highlight_style(
+                Style::default()
+                    .fg(self.theme.accent)
+                    .bg(self.theme.background),
+            )
             .select(self.selected_tab);
         f.render_widget(tabs, rect)
     }
@@ -1,5 +1,5 @@
 use crate::podcast;
-use crate::tui::Page;
+use crate::tui::{Page, Theme};
 use ratatui::widgets::ListItem;
 use ratatui::{prelude::*, widgets};
 use std::io;
@@ -7,23 +7,35 @@ use std::rc::Rc;
 use std::vec::Vec;
 
 pub struct EpisodesPage {
-    #[allow(dead_code)]
+    pods: Rc<Vec<podcast::Podcast>>,
     eps: Vec<Rc<podcast::Episode>>,
     ep_list_state: widgets::ListState,
+    filter: podcast::EpisodeFilter,
+    theme: Theme,
 }
 
+// The episode types the filter key cycles through, None meaning all of them.
+const EPISODE_TYPES: [Option<&str>; 4] = [None, Some("full"), Some("trailer"), Some("bonus")];
+
 impl Page for EpisodesPage {
     fn render(&mut self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect) {
         let mut titles = Vec::new();
         for ep in self.eps.iter() {
-            titles.push(ListItem::new(ep.title.clone()));
+            titles.push(ListItem::new(format!(
+                "{}{}{}{}{}",
+                ep.marker(),
+                ep.numbering(),
+                ep.title,
+                ep.starred_tag(),
+                ep.offline_tag()
+            )));
         }
         let list = widgets::List::new(titles)
-            .highlight_style(Style::default().fg(Color::Yellow))
+            .highlight_style(Style::default().fg(self.theme.accent))
             .highlight_symbol(">> ")
             .block(
                 widgets::Block::default()
-                    .title("Episodes")
+                    .title(self.title())
                     .borders(widgets::Borders::ALL),
             );
         f.render_stateful_widget(list, rect, &mut self.ep_list_state);
@@ -31,16 +43,72 @@ impl Page for EpisodesPage {
 }
 
 impl EpisodesPage {
-    pub fn new(pods: Rc<Vec<podcast::Podcast>>) -> EpisodesPage {
+    pub fn new(pods: Rc<Vec<podcast::Podcast>>, theme: Theme) -> EpisodesPage {
+        let ep_list_state = widgets::ListState::default().with_selected(Some(0));
+        let filter = podcast::EpisodeFilter::default();
+        EpisodesPage {
+            eps: EpisodesPage::collect_episodes(&pods, &filter),
+            pods,
+            ep_list_state,
+            filter,
+            theme,
+        }
+    }
+
+    fn collect_episodes(
+        pods: &[podcast::Podcast],
+        filter: &podcast::EpisodeFilter,
+    ) -> Vec<Rc<podcast::Episode>> {
         let mut eps = Vec::new();
         for pod in pods.iter() {
-            for ep in pod.episodes.iter() {
+            for ep in pod.episodes.iter().filter(|ep| filter.matches(ep)) {
                 eps.push(Rc::new(ep.clone()));
             }
         }
         eps.sort_by(|a, b| b.pub_date.cmp(&a.pub_date));
-        let ep_list_state = widgets::ListState::default().with_selected(Some(0));
-        EpisodesPage { eps, ep_list_state }
+        eps
+    }
+
+    fn title(&self) -> String {
+        let mut title = "Episodes".to_string();
+        if let Some(t) = &self.filter.episode_type {
+            title += &format!(" ({} only)", t);
+        }
+        if self.filter.hide_explicit {
+            title += " (no explicit)";
+        }
+        title
+    }
+
+    pub fn cycle_type_filter(&mut self) {
+        let current = EPISODE_TYPES
+            .iter()
+            .position(|t| *t == self.filter.episode_type.as_deref())
+            .unwrap_or(0);
+        self.filter.episode_type =
+            EPISODE_TYPES[(current + 1) % EPISODE_TYPES.len()].map(|t| t.to_string());
+        self.set_podcasts(self.pods.clone());
+    }
+
+    pub fn toggle_explicit_filter(&mut self) {
+        self.filter.hide_explicit = !self.filter.hide_explicit;
+        self.set_podcasts(self.pods.clone());
+    }
+
+    pub fn set_podcasts(&mut self, pods: Rc<Vec<podcast::Podcast>>) {
+        self.eps = EpisodesPage::collect_episodes(&pods, &self.filter);
+        self.pods = pods;
+        if let Some(i) = self.ep_list_state.selected() {
+            self.ep_list_state
+                .select(Some(i.min(self.eps.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn selected_episode(&self) -> Option<&podcast::Episode> {
+        self.ep_list_state
+            .selected()
+            .and_then(|i| self.eps.get(i))
+            .map(|ep| ep.as_ref())
     }
 
     pub fn select_next(&mut self) {
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Result};
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+
+// Everything a key can do in the TUI. The names in the config file's `[keys]` table are the
+// snake_case versions of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Quit,
+    Down,
+    Up,
+    PageUp,
+    PageDown,
+    FocusPodcasts,
+    FocusEpisodes,
+    Play,
+    Pause,
+    Stop,
+    SeekBackward,
+    SeekForward,
+    VolumeDown,
+    VolumeUp,
+    SpeedDown,
+    SpeedUp,
+    Next,
+    TogglePlayed,
+    Enqueue,
+    CycleTypeFilter,
+    ToggleExplicitFilter,
+    CycleTagFilter,
+    Dequeue,
+    MoveDown,
+    MoveUp,
+    NextTab,
+}
+
+const ACTIONS: &[(&str, KeyAction, &[&str])] = &[
+    ("quit", KeyAction::Quit, &["q"]),
+    ("down", KeyAction::Down, &["j"]),
+    ("up", KeyAction::Up, &["k"]),
+    ("page_up", KeyAction::PageUp, &["u"]),
+    ("page_down", KeyAction::PageDown, &["d"]),
+    ("focus_podcasts", KeyAction::FocusPodcasts, &["h"]),
+    ("focus_episodes", KeyAction::FocusEpisodes, &["l"]),
+    ("play", KeyAction::Play, &["p", "enter"]),
+    ("pause", KeyAction::Pause, &["space"]),
+    ("stop", KeyAction::Stop, &["s"]),
+    ("seek_backward", KeyAction::SeekBackward, &["left"]),
+    ("seek_forward", KeyAction::SeekForward, &["right"]),
+    ("volume_down", KeyAction::VolumeDown, &["-"]),
+    ("volume_up", KeyAction::VolumeUp, &["+", "="]),
+    ("speed_down", KeyAction::SpeedDown, &["["]),
+    ("speed_up", KeyAction::SpeedUp, &["]"]),
+    ("next", KeyAction::Next, &["n"]),
+    ("toggle_played", KeyAction::TogglePlayed, &["m"]),
+    ("enqueue", KeyAction::Enqueue, &["a"]),
+    ("cycle_type_filter", KeyAction::CycleTypeFilter, &["f"]),
+    (
+        "toggle_explicit_filter",
+        KeyAction::ToggleExplicitFilter,
+        &["e"],
+    ),
+    ("cycle_tag_filter", KeyAction::CycleTagFilter, &["t"]),
+    ("dequeue", KeyAction::Dequeue, &["x"]),
+    ("move_down", KeyAction::MoveDown, &["J"]),
+    ("move_up", KeyAction::MoveUp, &["K"]),
+    ("next_tab", KeyAction::NextTab, &["tab"]),
+];
+
+pub struct Keymap {
+    keys: HashMap<KeyCode, KeyAction>,
+}
+
+impl Keymap {
+    // The default bindings, with any action named in `overrides` moved to the keys given
+    // there instead. Keys are single characters or names like "enter", "space" and "left".
+    pub fn new(overrides: &HashMap<String, String>) -> Result<Keymap> {
+        for name in overrides.keys() {
+            if !ACTIONS.iter().any(|(n, _, _)| n == name) {
+                return Err(anyhow!("There's no TUI action called {:?}.", name));
+            }
+        }
+        let mut keys = HashMap::new();
+        for (name, action, defaults) in ACTIONS {
+            match overrides.get(*name) {
+                Some(key) => {
+                    keys.insert(parse_key(key)?, *action);
+                }
+                None => {
+                    for key in defaults.iter() {
+                        keys.entry(parse_key(key)?).or_insert(*action);
+                    }
+                }
+            }
+        }
+        Ok(Keymap { keys })
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<KeyAction> {
+        self.keys.get(&key).copied()
+    }
+}
+
+fn parse_key(key: &str) -> Result<KeyCode> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    Ok(match key.to_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "esc" | "escape" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => return Err(anyhow!("Don't know the key {:?}.", key)),
+    })
+}
@@ -1,42 +1,124 @@
+use crate::player::{Command, Player, Status};
 use crate::podcast;
-use crate::tui::Page;
-use anyhow::Result;
-use libmpv::MpvHandler;
-use ratatui::widgets::ListItem;
+use crate::tui::{Page, Theme};
 use ratatui::{prelude::*, widgets};
 use std::io;
-use std::rc::Rc;
-use std::vec::Vec;
+
+const SEEK_STEP: f64 = 30.0;
+const VOLUME_STEP: f64 = 5.0;
+const SPEED_STEP: f64 = 0.1;
 
 pub struct PlayerPage {
-    queue: Vec<podcast::Episode>,
-    mpv: MpvHandler,
+    player: Player,
+    status: Status,
+    vsplit: Layout,
+    theme: Theme,
 }
 
 impl Page for PlayerPage {
     fn render(&mut self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect) {
-        let mut titles = Vec::new();
-        for ep in self.queue.iter() {
-            titles.push(ListItem::new(ep.title.clone()));
-        }
-        let list = widgets::List::new(titles)
-            .highlight_style(Style::default().fg(Color::Yellow))
-            .highlight_symbol(">> ")
-            .block(
-                widgets::Block::default()
-                    .title("Episodes")
-                    .borders(widgets::Borders::ALL),
-            );
-        f.render_widget(list, rect);
+        let block = widgets::Block::default()
+            .borders(widgets::Borders::TOP)
+            .title("Player");
+        let rects = self.vsplit.split(block.inner(rect));
+        f.render_widget(block, rect);
+        f.render_widget(widgets::Paragraph::new(self.now_playing()), rects[0]);
+        let position = self.status.position;
+        let (ratio, total) = match self.status.duration {
+            Some(d) if d > 0.0 => ((position / d).clamp(0.0, 1.0), format_secs(d)),
+            _ => (0.0, "--:--".to_string()),
+        };
+        let gauge = widgets::Gauge::default()
+            .gauge_style(
+                Style::default()
+                    .fg(self.theme.accent)
+                    .bg(self.theme.background),
+            )
+            .ratio(ratio)
+            .label(format!("{} / {}", format_secs(position), total));
+        f.render_widget(gauge, rects[1]);
     }
 }
 
 impl PlayerPage {
-    pub fn new() -> Result<PlayerPage> {
-        let mpv = MpvHandler::new()?;
+    pub fn new(db_name: String, theme: Theme) -> PlayerPage {
+        let player = Player::spawn(db_name);
         PlayerPage {
-            queue: Vec::new(),
-            mpv,
+            status: player.status(),
+            player,
+            vsplit: Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Length(1)].as_ref()),
+            theme,
+        }
+    }
+
+    // Pulls the latest state from the player thread. Returns true when an episode stopped
+    // or finished since the last refresh, meaning its saved progress has changed.
+    pub fn refresh(&mut self) -> bool {
+        let status = self.player.status();
+        let changed = status.changes != self.status.changes;
+        self.status = status;
+        changed
+    }
+
+    pub fn play(&self, ep: podcast::Episode) {
+        self.player.send(Command::Load(Box::new(ep)));
+    }
+
+    pub fn toggle_pause(&self) {
+        self.player.send(Command::TogglePause);
+    }
+
+    pub fn stop(&self) {
+        self.player.send(Command::Stop);
+    }
+
+    pub fn next(&self) {
+        self.player.send(Command::Next);
+    }
+
+    pub fn seek_forward(&self) {
+        self.player.send(Command::Seek(SEEK_STEP));
+    }
+
+    pub fn seek_backward(&self) {
+        self.player.send(Command::Seek(-SEEK_STEP));
+    }
+
+    pub fn volume_up(&self) {
+        self.player.send(Command::Volume(VOLUME_STEP));
+    }
+
+    pub fn volume_down(&self) {
+        self.player.send(Command::Volume(-VOLUME_STEP));
+    }
+
+    pub fn speed_up(&self) {
+        self.player.send(Command::Speed(SPEED_STEP));
+    }
+
+    pub fn speed_down(&self) {
+        self.player.send(Command::Speed(-SPEED_STEP));
+    }
+
+    fn now_playing(&self) -> String {
+        if let Some(error) = &self.status.error {
+            return format!("Error: {}", error);
+        }
+        match &self.status.episode {
+            Some(ep) => format!(
+                "{} {}  vol {:.0}%  {:.1}x",
+                if self.status.paused { "⏸" } else { "▶" },
+                ep.title,
+                self.status.volume,
+                self.status.speed
+            ),
+            None => "Nothing playing.".to_string(),
         }
     }
 }
+
+fn format_secs(secs: f64) -> String {
+    podcast::format_time(secs.max(0.0) as i64)
+}
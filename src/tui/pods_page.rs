@@ -1,15 +1,19 @@
 use crate::podcast;
-use crate::tui::Page;
+use crate::tui::{Page, Theme};
 use ratatui::{prelude::*, widgets};
 use std::io;
 
 pub struct PodcastsPage {
+    // Every podcast, and the ones with the tag being filtered on, which are the ones shown.
+    all_pods: std::rc::Rc<Vec<podcast::Podcast>>,
     pods: std::rc::Rc<Vec<podcast::Podcast>>,
+    tag_filter: Option<String>,
     pod_list_state: widgets::ListState,
     ep_list_state: Vec<widgets::ListState>,
     pod_list_focused: bool,
     vsplit: Layout,
     hsplit: Layout,
+    theme: Theme,
 }
 
 impl Page for PodcastsPage {
@@ -23,14 +27,16 @@ impl Page for PodcastsPage {
 }
 
 impl PodcastsPage {
-    pub fn new(pods: std::rc::Rc<Vec<podcast::Podcast>>) -> PodcastsPage {
+    pub fn new(pods: std::rc::Rc<Vec<podcast::Podcast>>, theme: Theme) -> PodcastsPage {
         let pod_list_state = widgets::ListState::default().with_selected(Some(0));
         let mut ep_list_state = Vec::<widgets::ListState>::new();
         for _ in 0..pods.len() {
             ep_list_state.push(widgets::ListState::default().with_selected(Some(0)));
         }
         PodcastsPage {
+            all_pods: pods.clone(),
             pods,
+            tag_filter: None,
             pod_list_state,
             vsplit: Layout::default()
                 .direction(Direction::Horizontal)
@@ -40,9 +46,76 @@ impl PodcastsPage {
                 .constraints([Constraint::Percentage(33), Constraint::Min(0)].as_ref()),
             ep_list_state,
             pod_list_focused: true,
+            theme,
         }
     }
 
+    pub fn set_podcasts(&mut self, pods: std::rc::Rc<Vec<podcast::Podcast>>) {
+        // A tag nothing has any more can't be filtered on.
+        if let Some(tag) = &self.tag_filter {
+            if !pods.iter().any(|p| p.has_tag(tag)) {
+                self.tag_filter = None;
+            }
+        }
+        self.all_pods = pods;
+        self.show_podcasts(self.filtered_podcasts());
+    }
+
+    // Moves the filter on to the next tag in alphabetical order, and from the last one back
+    // to showing every podcast.
+    pub fn cycle_tag_filter(&mut self) {
+        let mut tags: Vec<&String> = self.all_pods.iter().flat_map(|p| &p.tags).collect();
+        tags.sort_by_key(|t| t.to_lowercase());
+        tags.dedup_by_key(|t| t.to_lowercase());
+        let next = match &self.tag_filter {
+            None => tags.first(),
+            Some(current) => tags
+                .iter()
+                .skip_while(|t| !t.eq_ignore_ascii_case(current))
+                .nth(1),
+        };
+        self.tag_filter = next.map(|t| t.to_string());
+        self.pod_list_state.select(Some(0));
+        self.ep_list_state.clear();
+        self.show_podcasts(self.filtered_podcasts());
+    }
+
+    fn filtered_podcasts(&self) -> std::rc::Rc<Vec<podcast::Podcast>> {
+        match &self.tag_filter {
+            Some(tag) => std::rc::Rc::new(
+                self.all_pods
+                    .iter()
+                    .filter(|p| p.has_tag(tag))
+                    .cloned()
+                    .collect(),
+            ),
+            None => self.all_pods.clone(),
+        }
+    }
+
+    fn show_podcasts(&mut self, pods: std::rc::Rc<Vec<podcast::Podcast>>) {
+        self.ep_list_state.resize(
+            pods.len(),
+            widgets::ListState::default().with_selected(Some(0)),
+        );
+        for (state, pod) in self.ep_list_state.iter_mut().zip(pods.iter()) {
+            if let Some(j) = state.selected() {
+                state.select(Some(j.min(pod.episodes.len().saturating_sub(1))));
+            }
+        }
+        if let Some(i) = self.pod_list_state.selected() {
+            self.pod_list_state
+                .select(Some(i.min(pods.len().saturating_sub(1))));
+        }
+        self.pods = pods;
+    }
+
+    pub fn selected_episode(&self) -> Option<&podcast::Episode> {
+        let i = self.pod_list_state.selected()?;
+        let j = self.ep_list_state.get(i)?.selected()?;
+        self.pods.get(i)?.episodes.get(j)
+    }
+
     pub fn select_next(&mut self) {
         if self.pod_list_focused {
             self.select_next_podcast();
@@ -173,7 +246,7 @@ impl PodcastsPage {
 
     pub fn style_if_focus(&self, invert: bool) -> Style {
         if self.pod_list_focused ^ invert {
-            Style::default().fg(Color::Yellow)
+            Style::default().fg(self.theme.accent)
         } else {
             Style::default()
         }
@@ -188,6 +261,10 @@ impl PodcastsPage {
         for pod in self.pods.iter() {
             items.push(widgets::ListItem::new(pod.title.clone()));
         }
+        let title = match &self.tag_filter {
+            Some(tag) => format!("Podcasts tagged {}", tag),
+            None => "Podcasts".to_string(),
+        };
         let pod_list = widgets::List::new(items)
             .highlight_style(self.style_if_focus(false))
             .highlight_symbol(">> ")
@@ -195,7 +272,7 @@ impl PodcastsPage {
                 widgets::Block::default()
                     .borders(widgets::Borders::TOP)
                     .border_style(self.style_if_focus(false))
-                    .title("Podcasts")
+                    .title(title)
                     .title_style(self.style_if_focus(false)),
             );
         f.render_stateful_widget(pod_list, rect, &mut self.pod_list_state);
@@ -203,7 +280,21 @@ impl PodcastsPage {
 
     fn render_desc_widget(&self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect) {
         let selected = self.pod_list_state.selected().unwrap();
-        let desc = widgets::Paragraph::new(self.pods[selected].description.clone())
+        let pod = &self.pods[selected];
+        let mut text = pod.description.clone();
+        if let Some(author) = &pod.author {
+            text += &format!("\n\nBy {}", author);
+        }
+        if !pod.categories.is_empty() {
+            text += &format!("\nCategories: {}", pod.categories.join(", "));
+        }
+        if !pod.tags.is_empty() {
+            text += &format!("\nTags: {}", pod.tags.join(", "));
+        }
+        if pod.explicit == Some(true) {
+            text += "\nExplicit";
+        }
+        let desc = widgets::Paragraph::new(text)
             .wrap(widgets::Wrap { trim: false })
             .block(
                 widgets::Block::default()
@@ -217,7 +308,14 @@ impl PodcastsPage {
         let selected = self.pod_list_state.selected().unwrap();
         let mut items = Vec::new();
         for ep in self.pods[selected].episodes.iter() {
-            items.push(widgets::ListItem::new(ep.title.clone()));
+            items.push(widgets::ListItem::new(format!(
+                "{}{}{}{}{}",
+                ep.marker(),
+                ep.numbering(),
+                ep.title,
+                ep.starred_tag(),
+                ep.offline_tag()
+            )));
         }
         let ep_list = widgets::List::new(items)
             .highlight_style(self.style_if_focus(true))
@@ -0,0 +1,112 @@
+use crate::podcast;
+use crate::tui::{Page, Theme};
+use ratatui::widgets::ListItem;
+use ratatui::{prelude::*, widgets};
+use std::io;
+use std::vec::Vec;
+
+pub struct QueuePage {
+    queue: Vec<podcast::Episode>,
+    queue_list_state: widgets::ListState,
+    theme: Theme,
+}
+
+impl Page for QueuePage {
+    fn render(&mut self, f: &mut Frame<CrosstermBackend<io::Stdout>>, rect: Rect) {
+        let mut titles = Vec::new();
+        for (i, ep) in self.queue.iter().enumerate() {
+            titles.push(ListItem::new(format!(
+                "{:>3}. {}{}{}{}",
+                i + 1,
+                ep.marker(),
+                ep.title,
+                ep.starred_tag(),
+                ep.offline_tag()
+            )));
+        }
+        let list = widgets::List::new(titles)
+            .highlight_style(Style::default().fg(self.theme.accent))
+            .highlight_symbol(">> ")
+            .block(
+                widgets::Block::default()
+                    .title("Up Next")
+                    .borders(widgets::Borders::ALL),
+            );
+        f.render_stateful_widget(list, rect, &mut self.queue_list_state);
+    }
+}
+
+impl QueuePage {
+    pub fn new(queue: Vec<podcast::Episode>, theme: Theme) -> QueuePage {
+        let queue_list_state = widgets::ListState::default().with_selected(Some(0));
+        QueuePage {
+            queue,
+            queue_list_state,
+            theme,
+        }
+    }
+
+    pub fn set_queue(&mut self, queue: Vec<podcast::Episode>) {
+        self.queue = queue;
+        if let Some(i) = self.queue_list_state.selected() {
+            self.select(i);
+        }
+    }
+
+    pub fn selected_episode(&self) -> Option<&podcast::Episode> {
+        self.queue_list_state
+            .selected()
+            .and_then(|i| self.queue.get(i))
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        self.queue_list_state.selected()
+    }
+
+    pub fn select(&mut self, i: usize) {
+        self.queue_list_state
+            .select(Some(i.min(self.queue.len().saturating_sub(1))));
+    }
+
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.queue_list_state.selected() {
+            if i + 1 < self.queue.len() {
+                self.queue_list_state.select(Some(i + 1));
+            } else {
+                self.queue_list_state.select(Some(0));
+            }
+        }
+    }
+
+    pub fn page_up(&mut self) {
+        if let Some(i) = self.queue_list_state.selected() {
+            if i > 10 {
+                self.queue_list_state.select(Some(i - 10));
+            } else {
+                self.queue_list_state.select(Some(0));
+            }
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if let Some(i) = self.queue_list_state.selected() {
+            if i > 0 {
+                self.queue_list_state.select(Some(i - 1));
+            } else {
+                self.queue_list_state
+                    .select(Some(self.queue.len().saturating_sub(1)));
+            }
+        }
+    }
+
+    pub fn page_down(&mut self) {
+        if let Some(i) = self.queue_list_state.selected() {
+            if i + 10 < self.queue.len() {
+                self.queue_list_state.select(Some(i + 10));
+            } else {
+                self.queue_list_state
+                    .select(Some(self.queue.len().saturating_sub(1)));
+            }
+        }
+    }
+}